@@ -0,0 +1,134 @@
+//! Atom feeds of releases.
+
+use super::{replace_links, Release};
+
+/// A release in a feed.
+pub struct Item {
+    /// Entry title, e.g. `name version`.
+    pub title: String,
+    /// Unique id of the entry.
+    pub id: String,
+    /// Link to the release, if any.
+    pub link: Option<String>,
+    /// The release, rendered as the entry content.
+    pub release: Release,
+}
+
+/// Render an Atom feed of releases, newest first.
+///
+/// Timestamps come from the release dates, so the output only changes when the
+/// changelogs do. Releases without a `YYYY-MM-DD` date are left out.
+pub fn atom(title: &str, id: &str, link: Option<&str>, items: Vec<Item>) -> String {
+    let mut items: Vec<(String, Item)> = items
+        .into_iter()
+        .filter_map(|item| Some((timestamp(&item.release)?, item)))
+        .collect();
+    // stable, so releases on the same day keep their changelog order
+    items.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+    feed += format!("  <title>{}</title>\n", escape(title)).as_str();
+    feed += format!("  <id>{}</id>\n", escape(id)).as_str();
+    if let Some(link) = link {
+        feed += format!("  <link href=\"{}\"/>\n", escape(link)).as_str();
+    }
+    let updated = match items.first() {
+        Some((updated, _)) => updated.clone(),
+        None => "1970-01-01T00:00:00Z".to_string(),
+    };
+    feed += format!("  <updated>{updated}</updated>\n").as_str();
+    feed += format!("  <author><name>{}</name></author>\n", escape(title)).as_str();
+
+    for (updated, item) in items {
+        feed += "  <entry>\n";
+        feed += format!("    <title>{}</title>\n", escape(&item.title)).as_str();
+        feed += format!("    <id>{}</id>\n", escape(&item.id)).as_str();
+        if let Some(link) = &item.link {
+            feed += format!("    <link href=\"{}\"/>\n", escape(link)).as_str();
+        }
+        feed += format!("    <updated>{updated}</updated>\n").as_str();
+        feed += format!(
+            "    <content type=\"html\">{}</content>\n",
+            escape(&html(&item.release))
+        )
+        .as_str();
+        feed += "  </entry>\n";
+    }
+
+    feed + "</feed>\n"
+}
+
+/// Whether a release has a date a feed entry can use.
+pub fn has_date(release: &Release) -> bool {
+    timestamp(release).is_some()
+}
+
+fn timestamp(release: &Release) -> Option<String> {
+    release.date.as_ref()?.split_whitespace().find_map(|word| {
+        let date = chrono::NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()?;
+        Some(format!("{}T00:00:00Z", date.format("%Y-%m-%d")))
+    })
+}
+
+fn html(release: &Release) -> String {
+    let mut html = vec![];
+    if !release.text.is_empty() {
+        html.push(format!("<p>{}</p>", inline(&release.text)));
+    }
+    for section in &release.sections {
+        html.push(format!("<h3>{}</h3>", inline(&section.name)));
+        if !section.text.is_empty() {
+            html.push(format!("<p>{}</p>", inline(&section.text)));
+        }
+        if !section.entries.is_empty() {
+            let entries: Vec<String> = section
+                .entries
+                .iter()
+                .map(|entry| format!("<li>{}</li>", inline(entry)))
+                .collect();
+            html.push(format!("<ul>{}</ul>", entries.join("")));
+        }
+    }
+
+    html.join("\n")
+}
+
+/// Render markdown links, inline code and bold text as HTML.
+fn inline(markdown: &str) -> String {
+    let html = replace_links(&escape(markdown), |text, url| {
+        format!("<a href=\"{url}\">{text}</a>")
+    });
+    let html = toggle(&html, "`", "code");
+    toggle(&html, "**", "strong")
+}
+
+/// Replace pairs of `delimiter` with an opening and closing `tag`.
+fn toggle(text: &str, delimiter: &str, tag: &str) -> String {
+    let parts: Vec<&str> = text.split(delimiter).collect();
+    // unbalanced delimiters are left alone
+    if parts.len().is_multiple_of(2) {
+        return text.to_string();
+    }
+
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            out += if !i.is_multiple_of(2) {
+                format!("<{tag}>")
+            } else {
+                format!("</{tag}>")
+            }
+            .as_str();
+        }
+        out += part;
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
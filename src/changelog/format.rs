@@ -0,0 +1,220 @@
+use super::{prepend, replace_links, today, Changelog, Document, Release};
+use crate::error::{IoContext, NanpaError, Result};
+use serde_json::{json, Value};
+use std::{fs, path};
+
+/// A changelog file format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Keep a Changelog markdown, `CHANGELOG.md`.
+    Markdown,
+    /// reStructuredText, `CHANGELOG.rst`.
+    Rst,
+    /// AsciiDoc, `CHANGELOG.adoc`.
+    Asciidoc,
+    /// An array of releases, `changelog.json`.
+    Json,
+}
+
+impl Format {
+    /// Parse a format name, as written in `.nanparc`.
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "rst" | "restructuredtext" => Ok(Self::Rst),
+            "asciidoc" | "adoc" => Ok(Self::Asciidoc),
+            "json" => Ok(Self::Json),
+            unknown => Err(NanpaError::usage(format!(
+                "unknown changelog format {unknown}"
+            ))),
+        }
+    }
+
+    /// Name of the format, as written in `.nanparc`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Rst => "rst",
+            Self::Asciidoc => "asciidoc",
+            Self::Json => "json",
+        }
+    }
+
+    /// Name of the changelog file in a package directory.
+    pub fn file(&self) -> &'static str {
+        match self {
+            Self::Markdown => "CHANGELOG.md",
+            Self::Rst => "CHANGELOG.rst",
+            Self::Asciidoc => "CHANGELOG.adoc",
+            Self::Json => "changelog.json",
+        }
+    }
+
+    /// Add the release of `changelog` as `version` to the changelog in
+    /// `dir`, returning its path.
+    ///
+    /// `nanpa changeset` edits markdown changelogs through [`Document`]
+    /// instead, to also fold in the `Unreleased` section and update
+    /// comparison links.
+    pub fn write(
+        &self,
+        dir: &path::Path,
+        changelog: &Changelog,
+        version: &str,
+    ) -> Result<path::PathBuf> {
+        let file = dir.join(self.file());
+        let existing = match fs::read_to_string(&file) {
+            Ok(existing) => Some(existing),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).at(&file),
+        };
+
+        let release = changelog.release(version.to_string(), Some(today()));
+        let output = match self {
+            Self::Markdown => {
+                let mut document = match existing {
+                    Some(existing) => Document::parse(&existing),
+                    None => Document::new(),
+                };
+                document.insert(release);
+                document.to_string()
+            }
+            Self::Rst => {
+                let existing = existing.unwrap_or("Changelog\n=========\n".to_string());
+                prepend(&existing, &rst(&release), |lines, i| {
+                    i + 1 < lines.len()
+                        && !lines[i].trim().is_empty()
+                        && !lines[i + 1].is_empty()
+                        && lines[i + 1].chars().all(|c| c == '-')
+                })
+            }
+            Self::Asciidoc => {
+                let existing = existing.unwrap_or("= Changelog\n".to_string());
+                prepend(&existing, &asciidoc(&release), |lines, i| {
+                    lines[i].starts_with("== ")
+                })
+            }
+            Self::Json => {
+                let mut releases: Vec<Value> = match existing {
+                    Some(existing) => match serde_json::from_str(&existing) {
+                        Ok(releases) => releases,
+                        Err(e) => return Err(NanpaError::changelog(&file, e)),
+                    },
+                    None => vec![],
+                };
+                releases.retain(|existing| existing["version"] != version);
+                releases.insert(0, json_release(changelog, &release));
+                serde_json::to_string_pretty(&releases)
+                    .map_err(|e| NanpaError::changelog(&file, e))?
+                    + "\n"
+            }
+        };
+
+        fs::write(&file, output).at(&file)?;
+        fs::canonicalize(&file).at(&file)
+    }
+}
+
+fn title(release: &Release) -> String {
+    match &release.date {
+        Some(date) => format!("{} - {date}", release.version),
+        None => release.version.clone(),
+    }
+}
+
+fn rst(release: &Release) -> String {
+    // inline code takes double backticks in reStructuredText
+    let inline = |text: &str| {
+        replace_links(&text.replace('`', "``"), |text, url| {
+            format!("`{text} <{url}>`__")
+        })
+    };
+    let underline = |text: &str, c: char| c.to_string().repeat(text.chars().count());
+
+    let title = title(release);
+    let mut blocks = vec![format!("{title}\n{}", underline(&title, '-'))];
+    if !release.text.is_empty() {
+        blocks.push(inline(&release.text));
+    }
+    for section in &release.sections {
+        blocks.push(format!(
+            "{}\n{}",
+            section.name,
+            underline(&section.name, '^')
+        ));
+        if !section.text.is_empty() {
+            blocks.push(inline(&section.text));
+        }
+        if !section.entries.is_empty() {
+            let entries: Vec<String> = section
+                .entries
+                .iter()
+                .map(|entry| format!("- {}", inline(entry)))
+                .collect();
+            blocks.push(entries.join("\n"));
+        }
+    }
+
+    blocks.join("\n\n") + "\n"
+}
+
+fn asciidoc(release: &Release) -> String {
+    let inline =
+        |text: &str| replace_links(text, |text, url| format!("{url}[{text}]")).replace("**", "*");
+
+    let mut blocks = vec![format!("== {}", title(release))];
+    if !release.text.is_empty() {
+        blocks.push(inline(&release.text));
+    }
+    for section in &release.sections {
+        blocks.push(format!("=== {}", section.name));
+        if !section.text.is_empty() {
+            blocks.push(inline(&section.text));
+        }
+        if !section.entries.is_empty() {
+            let entries: Vec<String> = section
+                .entries
+                .iter()
+                .map(|entry| format!("* {}", inline(entry)))
+                .collect();
+            blocks.push(entries.join("\n"));
+        }
+    }
+
+    blocks.join("\n\n") + "\n"
+}
+
+// built from the changes themselves, so entries keep their type and
+// references apart from the description
+fn json_release(changelog: &Changelog, release: &Release) -> Value {
+    let mut entries = vec![];
+    for (change_type, changes) in &changelog.sections {
+        // hidden types are left out, as in the other formats
+        if change_type.heading.is_none() {
+            continue;
+        }
+        for change in changes {
+            let mut entry = json!({
+                "type": change_type.name,
+                "description": change.description,
+            });
+            if let Some(pr) = &change.pr {
+                entry["pr"] = json!(pr);
+            }
+            if let Some(issue) = &change.issue {
+                entry["issue"] = json!(issue);
+            }
+            if let Some(commit) = &change.commit {
+                entry["commit"] = json!(commit.hash);
+                entry["author"] = json!(commit.author);
+            }
+            entries.push(entry);
+        }
+    }
+
+    json!({
+        "version": release.version,
+        "date": release.date,
+        "entries": entries,
+    })
+}
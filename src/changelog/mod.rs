@@ -0,0 +1,1198 @@
+//! Changelogs: collecting changes into a release, and reading and writing
+//! Keep a Changelog documents.
+
+use crate::error::{IoContext, NanpaError, Result};
+use crate::{git, package, template};
+use kdl::KdlNode;
+use std::{fmt, fs, path};
+
+pub mod feed;
+mod format;
+
+pub use format::Format;
+
+/// Changelog settings from the root config.
+#[derive(Clone, Default)]
+pub struct Config {
+    /// Change types, in section order.
+    pub types: Vec<ChangeType>,
+    /// Show the commit that added each changeset.
+    pub commits: bool,
+    /// Link template for commits, with `{id}`.
+    pub commit_url: Option<String>,
+    /// Link template for pull requests, with `{id}`.
+    pub pr_url: Option<String>,
+    /// Link template for issues, with `{id}`.
+    pub issue_url: Option<String>,
+    /// Repository URL, for comparison links.
+    pub repository: Option<String>,
+    /// Tag name pattern, with `{name}` and `{version}`.
+    pub tag_pattern: String,
+    /// Changelog template file and its contents.
+    pub template: Option<(path::PathBuf, String)>,
+}
+
+impl Config {
+    fn link(text: String, template: &Option<String>, id: &str) -> String {
+        match template {
+            Some(template) => format!("[{text}]({})", template.replace("{id}", id)),
+            None => text,
+        }
+    }
+
+    /// Rewrite the reference links at the bottom of a changelog, comparing
+    /// each release heading to the previous one.
+    pub fn compare(&self, document: &mut Document, package: &package::Package) {
+        let Some(repository) = &self.repository else {
+            return;
+        };
+        // private packages have no tags to compare
+        if package.private {
+            return;
+        }
+        let repository = repository.trim_end_matches('/');
+
+        let mut unreleased = false;
+        let mut versions = vec![];
+        for release in &document.releases {
+            if release.is_unreleased() {
+                unreleased = true;
+            } else {
+                versions.push(release.version.clone());
+            }
+        }
+        if versions.is_empty() && !unreleased {
+            return;
+        }
+
+        // keep references to anything other than releases
+        document.references.retain(|(label, _)| {
+            !label.eq_ignore_ascii_case("unreleased") && !versions.contains(label)
+        });
+
+        let tags: Vec<String> = versions
+            .iter()
+            .map(|version| package.fill_template(&self.tag_pattern, version))
+            .collect();
+        let mut references = vec![];
+        if unreleased {
+            references.push((
+                "Unreleased".to_string(),
+                match tags.first() {
+                    Some(tag) => format!("{repository}/compare/{tag}...HEAD"),
+                    None => format!("{repository}/commits/HEAD"),
+                },
+            ));
+        }
+        for (i, version) in versions.iter().enumerate() {
+            references.push((
+                version.clone(),
+                match tags.get(i + 1) {
+                    Some(previous) => format!("{repository}/compare/{previous}...{}", tags[i]),
+                    None => format!("{repository}/releases/tag/{}", tags[i]),
+                },
+            ));
+        }
+        references.append(&mut document.references);
+        document.references = references;
+    }
+
+    /// Read a change from a changeset node, with its pull request, issue and
+    /// commit references.
+    fn entry(
+        &self,
+        change: &KdlNode,
+        description: String,
+        file: &path::Path,
+        source: &str,
+    ) -> Result<Entry> {
+        let mut ids = vec![];
+        for key in ["pr", "issue"] {
+            ids.push(match change.get(key) {
+                Some(entry) => match entry.value().as_i64() {
+                    Some(id) => Some(id.to_string()),
+                    None => match entry.value().as_string() {
+                        Some(id) => Some(id.trim_start_matches('#').to_string()),
+                        None => {
+                            return Err(NanpaError::changeset_at(
+                                file,
+                                source,
+                                *entry.span(),
+                                format!("{key} must be a number or a string"),
+                            ))
+                        }
+                    },
+                },
+                None => None,
+            });
+        }
+        let commit = if self.commits {
+            let file = fs::canonicalize(file).at(file)?;
+            git::added_by(file.parent().unwrap(), &file)?
+        } else {
+            None
+        };
+
+        Ok(Entry {
+            description,
+            issue: ids.pop().unwrap(),
+            pr: ids.pop().unwrap(),
+            commit,
+        })
+    }
+}
+
+/// A change in a new release.
+#[derive(Clone)]
+pub struct Entry {
+    /// Description of the change.
+    pub description: String,
+    /// Pull request number.
+    pub pr: Option<String>,
+    /// Issue number.
+    pub issue: Option<String>,
+    /// Commit that added the changeset, with `changelog-commits`.
+    pub commit: Option<git::Addition>,
+}
+
+impl Entry {
+    /// Render the description followed by its references.
+    fn render(&self, config: &Config) -> String {
+        let mut refs = vec![];
+        for (id, template) in [(&self.pr, &config.pr_url), (&self.issue, &config.issue_url)] {
+            if let Some(id) = id {
+                refs.push(Config::link(format!("#{id}"), template, id));
+            }
+        }
+        if let Some(commit) = &self.commit {
+            refs.push(format!(
+                "{} by {}",
+                Config::link(
+                    commit.hash[..7].to_string(),
+                    &config.commit_url,
+                    &commit.hash
+                ),
+                commit.author
+            ));
+        }
+
+        if refs.is_empty() {
+            self.description.clone()
+        } else {
+            format!("{} ({})", self.description, refs.join(", "))
+        }
+    }
+
+    fn context(&self, config: &Config) -> template::Context {
+        let url = |template: &Option<String>, id: &Option<String>| match (template, id) {
+            (Some(template), Some(id)) => Some(template.replace("{id}", id)),
+            _ => None,
+        };
+        let hash = self.commit.as_ref().map(|commit| commit.hash.clone());
+
+        template::Context::default()
+            .text("description", &self.description)
+            .text("entry", self.render(config))
+            .optional("pr", self.pr.as_ref())
+            .optional("pr_url", url(&config.pr_url, &self.pr))
+            .optional("issue", self.issue.as_ref())
+            .optional("issue_url", url(&config.issue_url, &self.issue))
+            .optional("commit", hash.as_ref().map(|hash| &hash[..7]))
+            .optional("commit_url", url(&config.commit_url, &hash))
+            .optional("author", self.commit.as_ref().map(|commit| &commit.author))
+    }
+}
+
+/// A kind of change, with the heading of its changelog section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeType {
+    /// Name used in changesets.
+    pub name: String,
+    /// Section heading, or `None` to leave the type out of the changelog.
+    pub heading: Option<String>,
+}
+
+impl ChangeType {
+    /// Keep a Changelog's types.
+    pub fn defaults() -> Vec<Self> {
+        [
+            "Added",
+            "Changed",
+            "Deprecated",
+            "Removed",
+            "Fixed",
+            "Security",
+        ]
+        .into_iter()
+        .map(|heading| Self {
+            name: heading.to_lowercase(),
+            heading: Some(heading.to_string()),
+        })
+        .collect()
+    }
+
+    /// Parse a `type` option: a name, then a heading or `none`.
+    pub fn parse(words: &[&str]) -> Self {
+        let name = words[0].to_string();
+        let heading = match &words[1..] {
+            [] => {
+                let mut chars = name.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().collect::<String>() + chars.as_str())
+            }
+            ["none"] => None,
+            heading => Some(heading.join(" ")),
+        };
+
+        Self { name, heading }
+    }
+}
+
+/// Collects changes from changesets into a new release.
+pub struct Changelog {
+    config: Config,
+    /// Changes by type, in section order.
+    pub sections: Vec<(ChangeType, Vec<Entry>)>,
+}
+
+impl Changelog {
+    /// An empty changelog, with a section for each type in `config`.
+    pub fn new(config: Config) -> Self {
+        Self {
+            sections: config
+                .types
+                .clone()
+                .into_iter()
+                .map(|change_type| (change_type, vec![]))
+                .collect(),
+            config,
+        }
+    }
+
+    /// Add a change from a changeset node, read from `file` with contents
+    /// `source`.
+    pub fn push(&mut self, change: KdlNode, file: &path::Path, source: &str) -> Result<()> {
+        let error = |span: &miette::SourceSpan, message: String| {
+            NanpaError::changeset_at(file, source, *span, message)
+        };
+
+        let Some(change_type) = change.get("type") else {
+            return Err(error(
+                change.span(),
+                format!(
+                    "change type ({}) must be specified",
+                    self.names().join(", ")
+                ),
+            ));
+        };
+        let Some(description) = change.get(0) else {
+            return Err(error(
+                change.span(),
+                "change must have a description".to_string(),
+            ));
+        };
+        let Some(text) = description.value().as_string() else {
+            return Err(error(
+                description.span(),
+                "description must be a string".to_string(),
+            ));
+        };
+        let Some(name) = change_type.value().as_string() else {
+            return Err(error(
+                change_type.span(),
+                "type must be a string".to_string(),
+            ));
+        };
+
+        let entry = self.config.entry(&change, text.to_string(), file, source)?;
+        match self
+            .sections
+            .iter_mut()
+            .find(|(section, _)| section.name == name)
+        {
+            Some((_, entries)) => entries.push(entry),
+            None => {
+                return Err(error(
+                    change_type.span(),
+                    format!("unknown change type {name} ({})", self.names().join(", ")),
+                ))
+            }
+        };
+
+        Ok(())
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.sections
+            .iter()
+            .map(|(change_type, _)| change_type.name.as_str())
+            .collect()
+    }
+
+    /// The changes as a release, leaving out empty and hidden sections.
+    pub fn release(&self, version: String, date: Option<String>) -> Release {
+        let mut release = Release::new(version, date);
+
+        for (change_type, entries) in &self.sections {
+            if let Some(heading) = &change_type.heading {
+                if !entries.is_empty() {
+                    release.sections.push(Section {
+                        name: heading.clone(),
+                        bullet: "-".to_string(),
+                        text: String::new(),
+                        entries: entries
+                            .iter()
+                            .map(|entry| entry.render(&self.config))
+                            .collect(),
+                    });
+                }
+            }
+        }
+
+        release
+    }
+
+    /// Render the release of `package` as `version` in markdown, dated today,
+    /// with the changelog template if there is one.
+    pub fn markdown(&self, version: String, package: &package::Package) -> Result<String> {
+        let date = today();
+        let Some((file, template)) = &self.config.template else {
+            return Ok(self.release(version, Some(date)).to_string());
+        };
+
+        let mut sections = vec![];
+        let mut authors: Vec<&str> = vec![];
+        for (change_type, entries) in &self.sections {
+            let Some(heading) = &change_type.heading else {
+                continue;
+            };
+            if entries.is_empty() {
+                continue;
+            }
+            for entry in entries {
+                if let Some(commit) = &entry.commit {
+                    if !authors.contains(&commit.author.as_str()) {
+                        authors.push(&commit.author);
+                    }
+                }
+            }
+            sections.push(
+                template::Context::default()
+                    .text("heading", heading)
+                    .text("type", &change_type.name)
+                    .list(
+                        "entries",
+                        entries
+                            .iter()
+                            .map(|entry| entry.context(&self.config))
+                            .collect(),
+                    ),
+            );
+        }
+
+        let context = template::Context::default()
+            .text("version", &version)
+            .text("date", date)
+            .text("name", package.display_name())
+            .text(
+                "tag",
+                package.fill_template(&self.config.tag_pattern, &version),
+            )
+            .optional("repository", self.config.repository.as_ref())
+            .list("sections", sections)
+            .list(
+                "authors",
+                authors
+                    .into_iter()
+                    .map(|author| template::Context::default().text("author", author))
+                    .collect(),
+            );
+
+        template::render(template, &context).map_err(|e| NanpaError::changelog(file, e))
+    }
+}
+
+/// A Keep a Changelog document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    /// Text before the first release, usually the `# Changelog` title.
+    pub prologue: String,
+    /// Releases, newest first.
+    pub releases: Vec<Release>,
+    /// Reference link definitions at the bottom of the file.
+    pub references: Vec<(String, String)>,
+}
+
+/// A `## [version] - date` section of a changelog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    /// Version, or `Unreleased`.
+    pub version: String,
+    /// Text after the version in the heading, usually a date.
+    pub date: Option<String>,
+    /// Heading text after `## `, kept as is for existing releases.
+    pub title: String,
+    /// Text between the heading and the first section.
+    pub text: String,
+    /// Sections, in order.
+    pub sections: Vec<Section>,
+}
+
+/// A `### Type` section of a release.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    /// Heading text after `### `.
+    pub name: String,
+    /// List marker used for entries.
+    pub bullet: String,
+    /// Text between the heading and the first entry.
+    pub text: String,
+    /// List items, without the leading `- `.
+    pub entries: Vec<String>,
+}
+
+impl Document {
+    /// An empty changelog, titled `# Changelog`.
+    pub fn new() -> Self {
+        Self {
+            prologue: "# Changelog".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Read a changelog, or start a new one if it doesn't exist.
+    pub fn read(file: &path::Path) -> Result<Self> {
+        match fs::read_to_string(file) {
+            Ok(changelog) => Ok(Self::parse(&changelog)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e).at(file),
+        }
+    }
+
+    /// Parse a changelog. Anything that isn't recognized is kept as text.
+    pub fn parse(changelog: &str) -> Self {
+        let mut document = Self::default();
+        let mut prologue = vec![];
+        let mut fenced = false;
+        // a section with its own `####` headings, kept as text
+        let mut nested = false;
+
+        let mut lines: Vec<&str> = changelog.lines().collect();
+        // reference definitions at the very end of the file
+        let mut references = vec![];
+        while let Some(line) = lines.last() {
+            if line.trim().is_empty() {
+                lines.pop();
+            } else if let Some(reference) = parse_reference(line) {
+                references.push(reference);
+                lines.pop();
+            } else {
+                break;
+            }
+        }
+        references.reverse();
+        document.references = references;
+
+        for line in lines {
+            if line.trim_start().starts_with("```") {
+                fenced = !fenced;
+            }
+
+            if !fenced {
+                if let Some(title) = line.strip_prefix("## ") {
+                    if !document.releases.is_empty() || is_release_title(title) {
+                        document.releases.push(Release::parse_title(title));
+                        continue;
+                    }
+                }
+            }
+
+            let Some(release) = document.releases.last_mut() else {
+                prologue.push(line);
+                continue;
+            };
+
+            if !fenced {
+                if let Some(name) = line.strip_prefix("### ") {
+                    nested = false;
+                    release.sections.push(Section {
+                        name: name.trim().to_string(),
+                        bullet: "-".to_string(),
+                        text: String::new(),
+                        entries: vec![],
+                    });
+                    continue;
+                }
+            }
+
+            let Some(section) = release.sections.last_mut() else {
+                push_line(&mut release.text, line);
+                continue;
+            };
+
+            if !fenced && line.starts_with("#### ") {
+                nested = true;
+            }
+            if nested {
+                push_line(&mut section.text, line);
+                continue;
+            }
+
+            let item = ["- ", "* ", "+ "]
+                .into_iter()
+                .find_map(|bullet| Some((bullet, line.strip_prefix(bullet)?)));
+            match (item, section.entries.last_mut()) {
+                (Some((bullet, item)), _) if !fenced => {
+                    if section.entries.is_empty() {
+                        section.bullet = bullet.trim().to_string();
+                    }
+                    section.entries.push(item.to_string())
+                }
+                // continuation of a multi-line entry
+                (_, Some(entry)) if line.starts_with([' ', '\t']) || fenced => {
+                    entry.push('\n');
+                    entry.push_str(line);
+                }
+                (_, Some(_)) if line.trim().is_empty() => {}
+                _ => push_line(&mut section.text, line),
+            }
+        }
+
+        document.prologue = prologue.join("\n").trim().to_string();
+        for release in document.releases.iter_mut() {
+            release.text = release.text.trim().to_string();
+            for section in release.sections.iter_mut() {
+                section.text = section.text.trim().to_string();
+            }
+        }
+
+        document
+    }
+
+    /// Find a release by version, or the latest release if `version` is `None`.
+    pub fn release(&self, version: Option<&str>) -> Option<&Release> {
+        match version {
+            Some(version) => self.releases.iter().find(|release| {
+                release.version == version
+                    || release.version.trim_start_matches('v') == version.trim_start_matches('v')
+            }),
+            None => self
+                .releases
+                .iter()
+                .find(|release| !release.is_unreleased()),
+        }
+    }
+
+    /// Remove and return the `Unreleased` section, if any.
+    pub fn take_unreleased(&mut self) -> Option<Release> {
+        let position = self
+            .releases
+            .iter()
+            .position(|release| release.is_unreleased())?;
+        Some(self.releases.remove(position))
+    }
+
+    /// Add a release, replacing any existing release with the same version.
+    ///
+    /// Releases are kept newest first, after the `Unreleased` section.
+    pub fn insert(&mut self, release: Release) {
+        if let Some(existing) = self
+            .releases
+            .iter_mut()
+            .find(|existing| existing.version == release.version)
+        {
+            *existing = release;
+            return;
+        }
+
+        let position = self
+            .releases
+            .iter()
+            .position(|existing| !existing.is_unreleased() && release.is_newer_than(existing))
+            .unwrap_or(self.releases.len());
+        self.releases.insert(position, release);
+    }
+
+    /// Render the document with `block`, a release with a heading style of
+    /// its own, inserted as is before the first release heading of the same
+    /// level, after the `Unreleased` section, or after the other releases.
+    pub fn render_with(&self, block: &str) -> String {
+        let level = match block.trim_start().split_once(' ') {
+            Some((marks, _)) if !marks.is_empty() && marks.chars().all(|c| c == '#') => marks,
+            _ => "##",
+        };
+        let mut document = self.clone();
+        let references = std::mem::take(&mut document.references);
+
+        let mut changelog = prepend(&document.to_string(), block, |lines, i| {
+            lines[i]
+                .strip_prefix(level)
+                .and_then(|title| title.strip_prefix(' '))
+                .is_some_and(|title| {
+                    is_release_title(title) && !Release::parse_title(title).is_unreleased()
+                })
+        });
+        if !references.is_empty() {
+            let references: Vec<String> = references
+                .iter()
+                .map(|(label, url)| format!("[{label}]: {url}"))
+                .collect();
+            changelog = format!("{}\n\n{}\n", changelog.trim_end(), references.join("\n"));
+        }
+        changelog
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut blocks = vec![];
+        if !self.prologue.is_empty() {
+            blocks.push(self.prologue.clone());
+        }
+        for release in &self.releases {
+            blocks.push(release.to_string().trim_end().to_string());
+        }
+        if !self.references.is_empty() {
+            let references: Vec<String> = self
+                .references
+                .iter()
+                .map(|(label, url)| format!("[{label}]: {url}"))
+                .collect();
+            blocks.push(references.join("\n"));
+        }
+
+        writeln!(f, "{}", blocks.join("\n\n"))
+    }
+}
+
+impl Release {
+    /// An empty release, with a `[version] - date` heading.
+    pub fn new(version: String, date: Option<String>) -> Self {
+        let title = match &date {
+            Some(date) => format!("[{version}] - {date}"),
+            None => format!("[{version}]"),
+        };
+
+        Self {
+            version,
+            date,
+            title,
+            text: String::new(),
+            sections: vec![],
+        }
+    }
+
+    fn parse_title(title: &str) -> Self {
+        let title = title.trim();
+        // `[1.0.0] - 2020-01-01`, `1.0.0 - 2020-01-01`, `foo v1.0.0 (2020-01-01)`, ...
+        let words: Vec<&str> = title.split_whitespace().collect();
+        let index = words
+            .iter()
+            .position(|word| is_version(word.trim_matches(['[', ']'])))
+            .unwrap_or(0);
+        let version = words
+            .get(index)
+            .map(|word| word.trim_matches(['[', ']']).to_string())
+            .unwrap_or_default();
+        let date = words[(index + 1).min(words.len())..]
+            .join(" ")
+            .trim_matches(|c: char| c.is_whitespace() || "-–—()".contains(c))
+            .to_string();
+        let date = (!date.is_empty()).then_some(date);
+
+        Self {
+            version,
+            date,
+            title: title.to_string(),
+            text: String::new(),
+            sections: vec![],
+        }
+    }
+
+    /// Add entries from `other` missing from this release, e.g. ones added by
+    /// hand to the `Unreleased` section.
+    pub fn merge(&mut self, other: &Release) {
+        if self.text.is_empty() {
+            self.text = other.text.clone();
+        }
+        for section in &other.sections {
+            match self
+                .sections
+                .iter_mut()
+                .find(|existing| existing.name.eq_ignore_ascii_case(&section.name))
+            {
+                Some(existing) => {
+                    for entry in &section.entries {
+                        if !existing.entries.contains(entry) {
+                            existing.entries.push(entry.clone());
+                        }
+                    }
+                }
+                None => self.sections.push(section.clone()),
+            }
+        }
+    }
+
+    /// Whether this is the `Unreleased` section.
+    pub fn is_unreleased(&self) -> bool {
+        self.version.eq_ignore_ascii_case("unreleased")
+    }
+
+    fn is_newer_than(&self, other: &Release) -> bool {
+        match (
+            semver::Version::parse(self.version.trim_start_matches('v')),
+            semver::Version::parse(other.version.trim_start_matches('v')),
+        ) {
+            (Ok(version), Ok(other)) => version > other,
+            // unknown ordering, assume the new release is the newest
+            _ => true,
+        }
+    }
+
+    /// Render the release without its heading.
+    pub fn body(&self) -> String {
+        self.render_body("###")
+    }
+
+    fn render_body(&self, heading: &str) -> String {
+        let mut blocks = vec![];
+        if !self.text.is_empty() {
+            blocks.push(self.text.clone());
+        }
+        for section in &self.sections {
+            let mut block = format!("{heading} {}", section.name);
+            if !section.text.is_empty() {
+                block += format!("\n\n{}", section.text).as_str();
+            }
+            if !section.entries.is_empty() {
+                let entries: Vec<String> = section
+                    .entries
+                    .iter()
+                    .map(|entry| format!("{} {entry}", section.bullet))
+                    .collect();
+                block += format!("\n\n{}", entries.join("\n")).as_str();
+            }
+            blocks.push(block);
+        }
+
+        blocks.join("\n\n")
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.body();
+        if body.is_empty() {
+            writeln!(f, "## {}", self.title)
+        } else {
+            writeln!(f, "## {}\n\n{}", self.title, body)
+        }
+    }
+}
+
+/// Add a dated heading for a `nanpa changeset` run to an aggregated changelog,
+/// with a section for each released package, titled `name version`.
+pub fn aggregate(file: &path::Path, releases: &[(String, Release)]) -> Result<()> {
+    let mut document = Document::read(file)?;
+    add_run(&mut document, today(), releases);
+    fs::write(file, document.to_string()).at(file)
+}
+
+/// Add a run on `date` above the previous ones. Later runs on the same day
+/// are numbered, e.g. `2024-02-01 (2)`.
+fn add_run(document: &mut Document, date: String, releases: &[(String, Release)]) {
+    let runs = document
+        .releases
+        .iter()
+        .filter(|existing| existing.version == date)
+        .count();
+    let title = match runs {
+        0 => date.clone(),
+        runs => format!("{date} ({})", runs + 1),
+    };
+    let mut run = Release::parse_title(&title);
+    for (title, release) in releases {
+        run.sections.push(Section {
+            name: title.clone(),
+            bullet: "-".to_string(),
+            text: release.render_body("####"),
+            entries: vec![],
+        });
+    }
+
+    // not `insert`, which would replace the day's earlier runs
+    let position = document
+        .releases
+        .iter()
+        .position(|existing| !existing.is_unreleased())
+        .unwrap_or(document.releases.len());
+    document.releases.insert(position, run);
+}
+
+/// Insert `block` before the first line matching `is_release`, or at the end.
+fn prepend(existing: &str, block: &str, is_release: impl Fn(&[&str], usize) -> bool) -> String {
+    let lines: Vec<&str> = existing.lines().collect();
+    let position = (0..lines.len())
+        .find(|&i| is_release(&lines, i))
+        .unwrap_or(lines.len());
+
+    let head = lines[..position].join("\n");
+    let tail = lines[position..].join("\n");
+    let mut changelog = format!("{}\n\n{}", head.trim_end(), block.trim_end());
+    if !tail.is_empty() {
+        changelog += format!("\n\n{}", tail.trim_end()).as_str();
+    }
+    changelog + "\n"
+}
+
+/// Date of a release made now.
+fn today() -> String {
+    chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// Strip markdown formatting from rendered release notes.
+pub fn plain(markdown: &str) -> String {
+    let mut lines = vec![];
+    for line in markdown.lines() {
+        let line = match line.trim_start_matches('#') {
+            heading if heading.len() != line.len() && heading.starts_with(' ') => heading.trim(),
+            _ => line,
+        };
+
+        let plain = replace_links(line, |text, _| text.to_string());
+        lines.push(plain.replace("**", "").replace('`', ""));
+    }
+
+    lines.join("\n")
+}
+
+/// Replace markdown `[text](url)` links in `markdown` with `link(text, url)`.
+fn replace_links(markdown: &str, link: impl Fn(&str, &str) -> String) -> String {
+    let mut out = String::new();
+    let mut rest = markdown;
+    while let Some(start) = rest.find('[') {
+        let Some((text, tail)) = rest[start + 1..].split_once("](") else {
+            break;
+        };
+        let Some(end) = tail.find(')') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&link(text, &tail[..end]));
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn is_version(word: &str) -> bool {
+    word.eq_ignore_ascii_case("unreleased")
+        || word
+            .trim_start_matches('v')
+            .starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn is_release_title(title: &str) -> bool {
+    title
+        .split_whitespace()
+        .any(|word| is_version(word.trim_matches(['[', ']'])))
+}
+
+fn parse_reference(line: &str) -> Option<(String, String)> {
+    let (label, url) = line.strip_prefix('[')?.split_once("]: ")?;
+    Some((label.to_string(), url.trim().to_string()))
+}
+
+fn push_line(text: &mut String, line: &str) {
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGELOG: &str = "\
+# Changelog
+
+All notable changes to this project will be documented in this file.
+
+## [Unreleased]
+
+### Added
+
+- Pending feature
+
+## [1.1.0] - 2024-02-01
+
+Highlights of the release.
+
+### Added
+
+- New feature
+- Multi-line entry
+  continued here
+
+### Fixed
+
+* Crash on startup
+
+## [1.0.0] - 2024-01-01
+
+### Added
+
+- Initial release
+
+[Unreleased]: https://example.com/compare/v1.1.0...HEAD
+[1.1.0]: https://example.com/compare/v1.0.0...v1.1.0
+[1.0.0]: https://example.com/releases/tag/v1.0.0
+";
+
+    #[test]
+    fn round_trip() {
+        let document = Document::parse(CHANGELOG);
+        assert_eq!(document.to_string(), CHANGELOG);
+        assert_eq!(Document::parse(&document.to_string()), document);
+    }
+
+    #[test]
+    fn parses_releases() {
+        let document = Document::parse(CHANGELOG);
+        assert!(document.prologue.starts_with("# Changelog"));
+
+        let versions: Vec<&str> = document
+            .releases
+            .iter()
+            .map(|release| release.version.as_str())
+            .collect();
+        assert_eq!(versions, ["Unreleased", "1.1.0", "1.0.0"]);
+
+        let release = document.release(None).unwrap();
+        assert_eq!(release.version, "1.1.0");
+        assert_eq!(release.date.as_deref(), Some("2024-02-01"));
+        assert_eq!(release.text, "Highlights of the release.");
+        assert_eq!(release.sections[0].name, "Added");
+        assert_eq!(
+            release.sections[0].entries,
+            ["New feature", "Multi-line entry\n  continued here"]
+        );
+        assert_eq!(release.sections[1].bullet, "*");
+        assert_eq!(release.sections[1].entries, ["Crash on startup"]);
+    }
+
+    #[test]
+    fn parses_references() {
+        let document = Document::parse(CHANGELOG);
+        assert_eq!(document.references.len(), 3);
+        assert_eq!(
+            document.references[2],
+            (
+                "1.0.0".to_string(),
+                "https://example.com/releases/tag/v1.0.0".to_string()
+            )
+        );
+        // the footer stays below the releases
+        let release = document.release(Some("1.0.0")).unwrap();
+        assert_eq!(release.sections[0].entries, ["Initial release"]);
+        assert!(release.sections[0].text.is_empty());
+    }
+
+    #[test]
+    fn heading_in_prologue() {
+        let changelog =
+            "# Changelog\n\n## About\n\nSome notes.\n\n## 1.0.0\n\n### Added\n\n- Thing\n";
+        let mut document = Document::parse(changelog);
+        assert_eq!(document.prologue, "# Changelog\n\n## About\n\nSome notes.");
+        assert_eq!(document.releases.len(), 1);
+        assert_eq!(document.to_string(), changelog);
+
+        document.insert(Release::new("1.1.0".to_string(), None));
+        let changelog = document.to_string();
+        assert!(changelog.find("## About").unwrap() < changelog.find("## [1.1.0]").unwrap());
+    }
+
+    #[test]
+    fn fenced_code() {
+        let changelog = "\
+# Changelog
+
+## 1.0.0
+
+### Changed
+
+- Config now looks like:
+
+  ```
+  ## not a release 2.0.0
+  ### not a section
+  - not an entry
+  ```
+
+- Another entry
+";
+        let document = Document::parse(changelog);
+        assert_eq!(document.releases.len(), 1);
+        let section = &document.releases[0].sections[0];
+        assert_eq!(section.name, "Changed");
+        assert_eq!(section.entries.len(), 2);
+        assert!(section.entries[0].contains("## not a release 2.0.0"));
+        assert!(section.entries[0].contains("- not an entry"));
+        assert_eq!(section.entries[1], "Another entry");
+    }
+
+    #[test]
+    fn nested_sections() {
+        let changelog = "\
+# Changelog
+
+## 2024-02-01
+
+### a 1.1.0
+
+#### Added
+
+- New feature
+
+#### Fixed
+
+- Crash
+
+### b 0.2.0
+
+#### Added
+
+- Other feature
+";
+        let document = Document::parse(changelog);
+        let sections = &document.releases[0].sections;
+        assert_eq!(sections.len(), 2);
+        assert!(sections[0].entries.is_empty());
+        assert_eq!(
+            sections[0].text,
+            "#### Added\n\n- New feature\n\n#### Fixed\n\n- Crash"
+        );
+        assert_eq!(document.to_string(), changelog);
+    }
+
+    #[test]
+    fn insert_after_unreleased() {
+        let mut document = Document::parse(CHANGELOG);
+        let mut release = Release::new("1.2.0".to_string(), Some("2024-03-01".to_string()));
+        release.sections.push(Section {
+            name: "Added".to_string(),
+            bullet: "-".to_string(),
+            text: String::new(),
+            entries: vec!["Newer feature".to_string()],
+        });
+        document.insert(release);
+
+        let versions: Vec<&str> = document
+            .releases
+            .iter()
+            .map(|release| release.version.as_str())
+            .collect();
+        assert_eq!(versions, ["Unreleased", "1.2.0", "1.1.0", "1.0.0"]);
+        assert!(document.to_string().contains(
+            "## [Unreleased]\n\n### Added\n\n- Pending feature\n\n## [1.2.0] - 2024-03-01"
+        ));
+    }
+
+    #[test]
+    fn insert_replaces_and_orders() {
+        let mut document = Document::parse(CHANGELOG);
+        document.insert(Release::new("1.0.0".to_string(), None));
+        assert_eq!(document.releases.len(), 3);
+        assert!(document.releases[2].sections.is_empty());
+
+        document.insert(Release::new("1.0.5".to_string(), None));
+        assert_eq!(document.releases[2].version, "1.0.5");
+    }
+
+    #[test]
+    fn render_with_own_heading() {
+        let mut document = Document::parse("# Changelog\n\n# Release 1.0.0\n\n* Old");
+        document
+            .references
+            .push(("1.0.0".to_string(), "https://x".to_string()));
+        assert_eq!(
+            document.render_with("# Release 1.1.0\n\n* New\n"),
+            "# Changelog\n\n# Release 1.1.0\n\n* New\n\n# Release 1.0.0\n\n* Old\n\n[1.0.0]: https://x\n"
+        );
+
+        let document = Document::parse(CHANGELOG);
+        assert!(document
+            .render_with("Release 1.2.0\n")
+            .contains("- Pending feature\n\nRelease 1.2.0\n\n## [1.1.0]"));
+    }
+
+    #[test]
+    fn heading_per_run() {
+        let mut release = Release::new("1.1.0".to_string(), None);
+        release.sections.push(Section {
+            name: "Added".to_string(),
+            bullet: "-".to_string(),
+            text: String::new(),
+            entries: vec!["New feature".to_string()],
+        });
+        let releases = [("a 1.1.0".to_string(), release)];
+
+        let mut document = Document::new();
+        add_run(&mut document, "2024-02-01".to_string(), &releases);
+        add_run(&mut document, "2024-02-01".to_string(), &releases);
+        add_run(&mut document, "2024-02-01".to_string(), &releases);
+        let titles: Vec<&str> = document
+            .releases
+            .iter()
+            .map(|release| release.title.as_str())
+            .collect();
+        assert_eq!(titles, ["2024-02-01 (3)", "2024-02-01 (2)", "2024-02-01"]);
+        assert!(document
+            .to_string()
+            .starts_with("# Changelog\n\n## 2024-02-01 (3)\n\n### a 1.1.0\n\n#### Added\n\n- New feature\n\n## 2024-02-01 (2)"));
+        assert_eq!(Document::parse(&document.to_string()), document);
+    }
+
+    #[test]
+    fn take_unreleased() {
+        let mut document = Document::parse(CHANGELOG);
+        let unreleased = document.take_unreleased().unwrap();
+        assert_eq!(unreleased.sections[0].entries, ["Pending feature"]);
+        assert!(document.take_unreleased().is_none());
+        assert_eq!(document.releases[0].version, "1.1.0");
+    }
+
+    #[test]
+    fn merge_keeps_unreleased_entries() {
+        let mut document = Document::parse(CHANGELOG);
+        let unreleased = document.take_unreleased().unwrap();
+        let mut release = document.releases[0].clone();
+        release.merge(&unreleased);
+        assert_eq!(
+            release.sections[0].entries,
+            [
+                "New feature",
+                "Multi-line entry\n  continued here",
+                "Pending feature"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let document = Document::parse("");
+        assert_eq!(document, Document::default());
+        assert_eq!(Document::new().to_string(), "# Changelog\n");
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(
+            plain("### Fixed\n\n- **Crash** in [`foo`](https://example.com)"),
+            "Fixed\n\n- Crash in foo"
+        );
+    }
+}
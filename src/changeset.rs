@@ -0,0 +1,150 @@
+//! Changesets, the `.nanpa/*.kdl` files describing pending changes.
+
+use crate::changelog::{Changelog, Config};
+use crate::error::{IoContext, NanpaError, Result};
+use crate::package::{self, Package};
+use crate::version::Bump;
+use glob::glob;
+use kdl::{KdlDocument, KdlNode};
+use std::{fs, path};
+
+/// Changes pending release for a package.
+pub struct Pending {
+    /// Highest bump level among the changes.
+    pub bump: Bump,
+    /// The changes, by type.
+    pub changelog: Changelog,
+    // root changesets with the package's changes removed
+    pub(crate) rewrites: Vec<(path::PathBuf, KdlDocument)>,
+    // changesets left without changes, absolute
+    pub(crate) to_delete: Vec<path::PathBuf>,
+}
+
+/// Read the changes to `package` from the changesets in `root` and in the
+/// package directory, without modifying them.
+pub fn pending(package: &Package, root: &path::Path, config: Config) -> Result<Pending> {
+    let mut bump = Bump::None;
+    let mut changelog = Changelog::new(config);
+    let mut rewrites: Vec<(path::PathBuf, KdlDocument)> = vec![];
+    let mut to_delete: Vec<path::PathBuf> = vec![];
+
+    let location = fs::canonicalize(&package.location).at(&package.location)?;
+    for file in changesets(root)? {
+        let file = file?;
+        let (source, mut changeset) = read(&file)?;
+
+        let mut package_nodes: Vec<KdlNode> = vec![];
+        for node in changeset.nodes() {
+            let Some(entry) = node.get("package") else {
+                continue;
+            };
+            let Some(path) = entry.value().as_string() else {
+                return Err(NanpaError::changeset_at(
+                    &file,
+                    &source,
+                    *entry.span(),
+                    "package must be a string",
+                ));
+            };
+            let target = root.join(path);
+            if package::config(&target).is_none() {
+                return Err(NanpaError::changeset_at(
+                    &file,
+                    &source,
+                    *entry.span(),
+                    format!("{path} is not a package"),
+                ));
+            }
+            if fs::canonicalize(&target).at(&target)? == location {
+                package_nodes.push(node.clone());
+            }
+        }
+        if package_nodes.is_empty() {
+            continue;
+        }
+        for node in package_nodes {
+            add(&mut bump, &mut changelog, &node, &file, &source)?;
+            changeset.nodes_mut().retain(|x| *x != node);
+        }
+        let file = fs::canonicalize(&file).at(&file)?;
+        if changeset.nodes().is_empty() {
+            to_delete.push(file)
+        } else {
+            rewrites.push((file, changeset));
+        }
+    }
+
+    for file in changesets(&package.location)? {
+        let file = file?;
+        let (source, changeset) = read(&file)?;
+        for node in changeset.nodes() {
+            add(&mut bump, &mut changelog, node, &file, &source)?;
+        }
+        to_delete.push(fs::canonicalize(&file).at(&file)?);
+    }
+
+    Ok(Pending {
+        bump,
+        changelog,
+        rewrites,
+        to_delete,
+    })
+}
+
+/// Read and parse a changeset, returning its contents with the document.
+pub fn read(file: &path::Path) -> Result<(String, KdlDocument)> {
+    let source = fs::read_to_string(file).at(file)?;
+    match source.parse() {
+        Ok(changeset) => Ok((source, changeset)),
+        Err(e) => {
+            let e: kdl::KdlError = e;
+            let kind = e.kind.to_string();
+            let kind = kind.trim_end_matches('.');
+            let message = match e.label {
+                Some(label) => format!("{label}: {kind}"),
+                None => kind.to_string(),
+            };
+            Err(NanpaError::changeset_at(file, &source, e.span, message))
+        }
+    }
+}
+
+/// Add a change to the pending bump and changelog.
+fn add(
+    bump: &mut Bump,
+    changelog: &mut Changelog,
+    node: &KdlNode,
+    file: &path::Path,
+    source: &str,
+) -> Result<()> {
+    if node.get(0).is_none() {
+        return Ok(());
+    }
+    match Bump::parse(node.name().value()) {
+        Some(level) => {
+            *bump = (*bump).max(level);
+            changelog.push(node.clone(), file, source)
+        }
+        None => Err(NanpaError::changeset_at(
+            file,
+            source,
+            *node.name().span(),
+            format!("unknown keyword {}", node.name().value()),
+        )),
+    }
+}
+
+/// Whether `path` is a changeset file, in a `.nanpa` directory.
+pub fn is_changeset(path: &path::Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "kdl")
+        && path
+            .parent()
+            .and_then(path::Path::file_name)
+            .is_some_and(|parent| parent == ".nanpa")
+}
+
+/// Changesets in `dir`.
+fn changesets(dir: &path::Path) -> Result<glob::Paths> {
+    let pattern = glob::Pattern::escape(&dir.to_string_lossy()) + "/.nanpa/*.kdl";
+    glob(&pattern).map_err(|e| NanpaError::usage(format!("{}: {e}", dir.display())))
+}
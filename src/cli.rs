@@ -1,7 +1,11 @@
-use crate::nanpa;
-use anyhow::Result;
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
+use nanpa::{
+    error::Result,
+    version::{Bump, Manual},
+    Bumped, ChangesetOptions, Filter, Nanpa, NanpaError,
+};
+use std::env;
 
 #[derive(Parser)]
 #[command(about, version, infer_subcommands = true)]
@@ -13,7 +17,15 @@ pub struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Show current package version
-    Version,
+    Version {
+        /// Show private packages (the default)
+        #[arg(long, overrides_with = "exclude_private")]
+        include_private: bool,
+
+        /// Hide private packages
+        #[arg(long, overrides_with = "include_private")]
+        exclude_private: bool,
+    },
     /// Bump package version from changesets and generate changelog entry
     Changeset {
         package: Option<String>,
@@ -25,11 +37,51 @@ enum Commands {
         /// Accept changeset without passing through editor
         #[arg(short)]
         yes: bool,
+
+        /// Commit the files modified by the release
+        #[arg(long)]
+        commit: bool,
+
+        /// Create an annotated tag for each released package
+        #[arg(long, requires = "commit")]
+        tag: bool,
+    },
+    /// Show unreleased changes from pending changesets
+    Changelog {
+        package: Option<String>,
+
+        /// Write changes to the Unreleased section of the changelog
+        #[arg(long)]
+        unreleased: bool,
+    },
+    /// Print release notes for a version from the changelog
+    Notes {
+        /// Version to print, defaults to the latest release
+        version: Option<String>,
+
+        #[arg(long, short)]
+        package: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: NotesFormat,
+
+        /// Omit the release heading
+        #[arg(long)]
+        no_heading: bool,
+    },
+    /// Write an Atom feed of releases from the changelog
+    Feed {
+        package: Option<String>,
+
+        /// Feed file to write, defaults to releases.xml in the package or root
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<String>,
     },
     /// Add a changeset
     Add {
-        #[arg(value_enum)]
-        bump: SemverVersionAdd,
+        #[arg(value_enum, required_unless_present = "from_commits")]
+        bump: Option<SemverVersionAdd>,
 
         #[arg(long, short)]
         package: Option<String>,
@@ -39,35 +91,63 @@ enum Commands {
 
         #[arg(long, short)]
         message: Option<String>,
+
+        /// Generate changesets from Conventional Commits in a git revision range
+        #[arg(
+            long,
+            value_name = "REV_RANGE",
+            conflicts_with_all = ["bump", "package", "type", "message"]
+        )]
+        from_commits: Option<String>,
     },
     /// Manually bump package version
-    #[command(args_conflicts_with_subcommands = true)]
+    #[command(subcommand_negates_reqs = true, arg_required_else_help = true)]
     Bump {
         #[command(subcommand)]
         semver_version: Option<SemverVersion>,
 
-        #[command(flatten)]
-        custom_version: Option<CustomVersion>,
+        /// Custom version to set instead of bumping
+        #[arg(value_name = "VERSION", required = true)]
+        custom_version: Option<String>,
 
         package: Option<String>,
+
+        #[command(flatten)]
+        selectors: Selectors,
+    },
+    /// Check that every package changed since a git ref has a changeset
+    Check {
+        /// Git ref to compare the working tree against
+        #[arg(long, value_name = "REF")]
+        since: String,
+
+        /// Ignore changed files matching a glob, relative to the root
+        #[arg(long, value_name = "GLOB")]
+        ignore: Vec<String>,
     },
+    /// Show a package's resolved config and where each option was set
+    Config { package: Option<String> },
     /// List supported languages
     ListLanguages,
 }
 
 #[derive(Subcommand)]
-pub enum SemverVersion {
+enum SemverVersion {
     #[command(alias = "x")]
-    Major,
+    Major(Target),
     #[command(alias = "y")]
-    Minor,
+    Minor(Target),
     #[command(alias = "z")]
-    Patch,
+    Patch(Target),
     Prerelease(Prerelease),
+    /// Post-release (pep440 only)
+    Post(Target),
+    /// Developmental release (pep440 only)
+    Dev(Target),
 }
 
 #[derive(ValueEnum, Clone)]
-pub enum SemverVersionAdd {
+enum SemverVersionAdd {
     #[value(alias = "x")]
     Major,
     #[value(alias = "y")]
@@ -76,56 +156,275 @@ pub enum SemverVersionAdd {
     Patch,
 }
 
+#[derive(ValueEnum, Clone, PartialEq)]
+enum NotesFormat {
+    Markdown,
+    #[value(alias = "text")]
+    Plain,
+}
+
 #[derive(Args)]
-pub struct Prerelease {
+struct Prerelease {
     pub version: String,
+
+    pub package: Option<String>,
 }
 
+/// Names of the bump levels, never taken as a custom version.
+const LEVELS: [&str; 10] = [
+    "major",
+    "minor",
+    "patch",
+    "x",
+    "y",
+    "z",
+    "prerelease",
+    "pre",
+    "post",
+    "dev",
+];
+
 #[derive(Args)]
-struct CustomVersion {
-    pub version: String,
+struct Target {
+    pub package: Option<String>,
+}
+
+/// Packages chosen for `nanpa bump`, accepted before or after the bump level.
+#[derive(Args)]
+struct Selectors {
+    /// Bump every package
+    #[arg(long, global = true)]
+    all: bool,
+
+    /// Bump packages by name, or by a glob of paths relative to the root
+    #[arg(long, global = true, value_name = "GLOB_OR_NAME")]
+    filter: Vec<String>,
+
+    /// Bump packages with files changed since a git ref
+    #[arg(long, global = true, value_name = "REF")]
+    changed_since: Option<String>,
 }
 
 pub fn command() -> Result<()> {
     let cli = Cli::parse();
-    let nanpa = nanpa::new()?;
+    if let Ok(dir) = env::current_dir() {
+        if nanpa::package::config(&dir).is_none() {
+            eprintln!("current directory does not contain .nanparc, searching up");
+        }
+    }
+    let nanpa = Nanpa::new()?;
+    for (config, dir) in nanpa.skipped() {
+        eprintln!(
+            "{}: skipping {}, no .nanparc",
+            config.display(),
+            dir.display()
+        );
+    }
 
     match &cli.command {
         Commands::Bump {
             semver_version,
             custom_version,
             package,
+            selectors,
         } => {
-            if let Some(version) = semver_version {
-                nanpa.bump_semver(version, package.clone())?;
-            } else {
-                nanpa.bump_custom(
-                    custom_version.as_ref().unwrap().version.clone(),
-                    package.clone(),
-                )?;
+            let filter = Filter {
+                all: selectors.all,
+                patterns: selectors.filter.clone(),
+                changed_since: selectors.changed_since.clone(),
+            };
+            let bumped = match (semver_version, custom_version) {
+                (Some(version), None) => {
+                    let (manual, target) = match version {
+                        SemverVersion::Major(target) => (Manual::Major, &target.package),
+                        SemverVersion::Minor(target) => (Manual::Minor, &target.package),
+                        SemverVersion::Patch(target) => (Manual::Patch, &target.package),
+                        SemverVersion::Prerelease(x) => {
+                            (Manual::Prerelease(x.version.clone()), &x.package)
+                        }
+                        SemverVersion::Post(target) => (Manual::Post, &target.package),
+                        SemverVersion::Dev(target) => (Manual::Dev, &target.package),
+                    };
+                    nanpa.bump(
+                        &manual,
+                        from_cwd(target.as_ref().or(package.as_ref())),
+                        &filter,
+                    )?
+                }
+                (None, Some(version)) if LEVELS.contains(&version.as_str()) => {
+                    return Err(NanpaError::usage(format!(
+                        "{version} is a bump level, not a custom version"
+                    )))
+                }
+                (None, Some(version)) => {
+                    nanpa.bump_custom(version.clone(), from_cwd(package.as_ref()), &filter)?
+                }
+                _ => {
+                    return Err(NanpaError::usage(
+                        "a custom version can't be combined with a bump level",
+                    ))
+                }
+            };
+            for Bumped {
+                package, version, ..
+            } in bumped
+            {
+                println!(
+                    "{}: {} -> {version}",
+                    package.location.display(),
+                    package.version.unwrap_or_default(),
+                );
             }
         }
-        Commands::Version => {
+        Commands::Version {
+            include_private,
+            exclude_private,
+        } => {
+            // the flags override each other, the last one given wins
+            let hide_private = *exclude_private && !*include_private;
             let versions = nanpa.packages();
             for (location, package) in versions {
+                if hide_private && package.private {
+                    continue;
+                }
                 println!("{}: {}", location, package.version.unwrap());
             }
         }
-        Commands::Changeset { package, pre, yes } => {
-            nanpa.changesets(package.clone(), pre.clone(), yes.clone())?
+        Commands::Changeset {
+            package,
+            pre,
+            yes,
+            commit,
+            tag,
+        } => {
+            let report = nanpa.changesets(
+                from_cwd(package.as_ref()),
+                &ChangesetOptions {
+                    pre: pre.clone(),
+                    yes: *yes,
+                    commit: *commit,
+                    tag: *tag,
+                },
+            )?;
+            for package in report.unchanged {
+                println!("{}: no changesets found", package.location.display());
+            }
+            for package in report.aborted {
+                println!(
+                    "{}: no changelog found, aborting",
+                    package.location.display()
+                );
+            }
+            for release in report.releases {
+                println!(
+                    "{}: {} -> {}",
+                    release.package.location.display(),
+                    release.package.version.unwrap_or_default(),
+                    release.version,
+                );
+            }
+            if let Some(file) = report.root_changelog {
+                println!("{}: updated", file.display());
+            }
+            if let Some(message) = report.commit {
+                println!("committed {}", message.lines().next().unwrap_or_default());
+            }
+            for tag in report.tags {
+                println!("tagged {tag}");
+            }
+        }
+        Commands::Changelog {
+            package,
+            unreleased,
+        } => {
+            for (package, release) in nanpa.changelog(from_cwd(package.as_ref()), *unreleased)? {
+                if *unreleased {
+                    println!("{}: updated unreleased changes", package.location.display());
+                } else {
+                    print!("{release}");
+                }
+            }
+        }
+        Commands::Notes {
+            version,
+            package,
+            format,
+            no_heading,
+        } => println!(
+            "{}",
+            nanpa.notes(
+                from_cwd(package.as_ref()),
+                version.clone(),
+                *format == NotesFormat::Plain,
+                *no_heading,
+            )?
+        ),
+        Commands::Feed { package, output } => {
+            let (output, skipped) = nanpa.feed(from_cwd(package.as_ref()), output.clone())?;
+            for (file, version) in skipped {
+                eprintln!("{}: skipping {version}, no date in heading", file.display());
+            }
+            println!("wrote {}", output.display());
         }
         Commands::Add {
             bump,
             package,
             change_type,
             message,
+            from_commits,
         } => {
-            nanpa.add(
-                package.clone(),
-                bump.clone(),
-                change_type.clone(),
-                message.clone(),
-            )?;
+            if let Some(range) = from_commits {
+                let report = nanpa.add_from_commits(range.clone())?;
+                for (hash, change_type) in &report.undeclared {
+                    eprintln!(
+                        "{}: skipping, change type {change_type} is not declared",
+                        &hash[..7]
+                    );
+                }
+                for (hash, file) in &report.written {
+                    println!("{}: {}", &hash[..7], file.display());
+                }
+                if report.written.is_empty() && report.undeclared.is_empty() {
+                    println!("no conventional commits found in {range}");
+                }
+            } else if nanpa
+                .add(
+                    package.clone(),
+                    match bump.clone().unwrap() {
+                        SemverVersionAdd::Major => Bump::Major,
+                        SemverVersionAdd::Minor => Bump::Minor,
+                        SemverVersionAdd::Patch => Bump::Patch,
+                    },
+                    change_type.clone(),
+                    message.clone(),
+                )?
+                .is_none()
+            {
+                println!("empty changeset, aborting");
+            }
+        }
+        Commands::Check { since, ignore } => {
+            nanpa.check(since.clone(), ignore.clone())?;
+            println!("all changed packages have changesets");
+        }
+        Commands::Config { package } => {
+            let configs = nanpa.resolved_config(from_cwd(package.as_ref()))?;
+            for (i, (location, options)) in configs.iter().enumerate() {
+                if configs.len() > 1 {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("# {location}");
+                }
+                let width = options
+                    .iter()
+                    .map(|(line, _)| line.len())
+                    .max()
+                    .unwrap_or(0);
+                for (line, source) in options {
+                    println!("{line:width$}  # {source}");
+                }
+            }
         }
         Commands::ListLanguages => {
             println!("{}", "Supported languages:".bold().underline());
@@ -147,3 +446,134 @@ pub fn command() -> Result<()> {
 
     Ok(())
 }
+
+/// Make a package path given on the command line, relative to the current
+/// directory, absolute. The library resolves relative paths against the root.
+fn from_cwd(package: Option<&String>) -> Option<String> {
+    package.map(|path| match env::current_dir() {
+        Ok(dir) => dir.join(path).to_string_lossy().into_owned(),
+        Err(_) => path.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(
+        args: &[&str],
+    ) -> (
+        Option<SemverVersion>,
+        Option<String>,
+        Option<String>,
+        Selectors,
+    ) {
+        let cli = Cli::try_parse_from([&["nanpa", "bump"], args].concat())
+            .unwrap_or_else(|e| panic!("{args:?}: {e}"));
+        let Commands::Bump {
+            semver_version,
+            custom_version,
+            package,
+            selectors,
+        } = cli.command
+        else {
+            panic!("{args:?}: not a bump");
+        };
+        (semver_version, custom_version, package, selectors)
+    }
+
+    fn target(version: &Option<SemverVersion>) -> Option<String> {
+        match version {
+            Some(SemverVersion::Patch(target)) | Some(SemverVersion::Minor(target)) => {
+                target.package.clone()
+            }
+            Some(SemverVersion::Prerelease(pre)) => pre.package.clone(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn selector_before_level() {
+        let (version, custom, package, selectors) = bump(&["--all", "patch"]);
+        assert!(matches!(version, Some(SemverVersion::Patch(_))));
+        assert_eq!(custom, None);
+        assert_eq!(package, None);
+        assert!(selectors.all);
+
+        let (version, custom, _, selectors) = bump(&["--filter", "a", "minor"]);
+        assert!(matches!(version, Some(SemverVersion::Minor(_))));
+        assert_eq!(custom, None);
+        assert_eq!(selectors.filter, ["a"]);
+    }
+
+    #[test]
+    fn selector_after_level() {
+        let (version, _, _, selectors) = bump(&["patch", "--all"]);
+        assert!(matches!(version, Some(SemverVersion::Patch(_))));
+        assert!(selectors.all);
+
+        let (version, _, _, selectors) = bump(&["minor", "--filter", "a", "--changed-since", "v1"]);
+        assert!(matches!(version, Some(SemverVersion::Minor(_))));
+        assert_eq!(selectors.filter, ["a"]);
+        assert_eq!(selectors.changed_since.as_deref(), Some("v1"));
+    }
+
+    #[test]
+    fn package_after_level() {
+        let (version, _, _, _) = bump(&["patch", "crates/a"]);
+        assert_eq!(target(&version).as_deref(), Some("crates/a"));
+
+        let (version, _, _, _) = bump(&["prerelease", "rc", "crates/a"]);
+        assert_eq!(target(&version).as_deref(), Some("crates/a"));
+    }
+
+    #[test]
+    fn custom_version() {
+        let (version, custom, package, _) = bump(&["2.0.0", "crates/a"]);
+        assert!(version.is_none());
+        assert_eq!(custom.as_deref(), Some("2.0.0"));
+        assert_eq!(package.as_deref(), Some("crates/a"));
+
+        let (version, custom, _, selectors) = bump(&["--all", "1.2.3"]);
+        assert!(version.is_none());
+        assert_eq!(custom.as_deref(), Some("1.2.3"));
+        assert!(selectors.all);
+
+        let (_, custom, _, selectors) = bump(&["1.2.3", "--filter", "a"]);
+        assert_eq!(custom.as_deref(), Some("1.2.3"));
+        assert_eq!(selectors.filter, ["a"]);
+    }
+
+    #[test]
+    fn level_or_version_required() {
+        assert!(Cli::try_parse_from(["nanpa", "bump"]).is_err());
+        assert!(Cli::try_parse_from(["nanpa", "bump", "--all"]).is_err());
+    }
+
+    #[test]
+    fn private_flags_override() {
+        let flags =
+            |args: &[&str]| match Cli::try_parse_from([&["nanpa", "version"], args].concat())
+                .unwrap()
+                .command
+            {
+                Commands::Version {
+                    include_private,
+                    exclude_private,
+                } => (include_private, exclude_private),
+                _ => panic!("not version"),
+            };
+        assert_eq!(flags(&[]), (false, false));
+        assert_eq!(flags(&["--exclude-private"]), (false, true));
+        assert_eq!(
+            flags(&["--exclude-private", "--include-private"]),
+            (true, false)
+        );
+    }
+
+    #[test]
+    fn definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,134 @@
+use crate::version::Bump;
+
+/// A change parsed from a Conventional Commits message.
+pub struct Change {
+    pub bump: Bump,
+    pub change_type: &'static str,
+    pub description: String,
+}
+
+/// Parse a commit message following <https://www.conventionalcommits.org>.
+///
+/// Returns `None` for messages that don't follow the format, and for commit
+/// types that don't belong in a changelog (`docs`, `chore`, `ci`, etc.).
+pub fn parse(message: &str) -> Option<Change> {
+    let (header, body) = message.split_once('\n').unwrap_or((message, ""));
+    let (prefix, description) = header.split_once(':')?;
+    let description = description.trim();
+    if description.is_empty() {
+        return None;
+    }
+
+    let (prefix, breaking) = match prefix.strip_suffix('!') {
+        Some(prefix) => (prefix, true),
+        None => (prefix, false),
+    };
+    let (kind, scope) = match prefix.split_once('(') {
+        Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?)),
+        None => (prefix, None),
+    };
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    let breaking = breaking
+        || body.lines().any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+
+    let (bump, change_type) = match kind.to_lowercase().as_str() {
+        "feat" | "feature" => (Bump::Minor, "added"),
+        "fix" if scope == Some("security") => (Bump::Patch, "security"),
+        "fix" => (Bump::Patch, "fixed"),
+        "security" => (Bump::Patch, "security"),
+        "perf" | "refactor" | "revert" => (Bump::Patch, "changed"),
+        "deprecate" => (Bump::Patch, "deprecated"),
+        "remove" => (Bump::Patch, "removed"),
+        _ if breaking => (Bump::Major, "changed"),
+        _ => return None,
+    };
+
+    Some(Change {
+        bump: if breaking { Bump::Major } else { bump },
+        change_type,
+        description: description.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(message: &str) -> Option<(Bump, &'static str, String)> {
+        parse(message).map(|change| (change.bump, change.change_type, change.description))
+    }
+
+    #[test]
+    fn types() {
+        let cases = [
+            ("feat: add thing", Bump::Minor, "added"),
+            ("Feature: add thing", Bump::Minor, "added"),
+            ("fix: crash", Bump::Patch, "fixed"),
+            ("fix(security): overflow", Bump::Patch, "security"),
+            ("perf(parser): faster", Bump::Patch, "changed"),
+            ("deprecate: old flag", Bump::Patch, "deprecated"),
+            ("remove: old flag", Bump::Patch, "removed"),
+        ];
+        for (message, bump, change_type) in cases {
+            let (b, t, _) = change(message).unwrap();
+            assert_eq!((b, t), (bump, change_type), "{message}");
+        }
+    }
+
+    #[test]
+    fn description() {
+        let (_, _, description) = change("fix(cli):   trailing spaces  \n\nbody").unwrap();
+        assert_eq!(description, "trailing spaces");
+    }
+
+    #[test]
+    fn breaking() {
+        assert_eq!(change("feat!: new api").unwrap().0, Bump::Major);
+        assert_eq!(change("fix(core)!: new api").unwrap().0, Bump::Major);
+        assert_eq!(
+            change("fix: thing\n\nBREAKING CHANGE: config moved")
+                .unwrap()
+                .0,
+            Bump::Major
+        );
+        assert_eq!(
+            change("refactor: thing\n\nBREAKING-CHANGE: gone")
+                .unwrap()
+                .0,
+            Bump::Major
+        );
+        // types left out of changelogs still count when breaking
+        assert_eq!(
+            change("chore!: drop old rust").unwrap(),
+            (Bump::Major, "changed", "drop old rust".to_string())
+        );
+        // only a footer line counts
+        assert_eq!(
+            change("fix: thing\n\nmentions BREAKING CHANGE: inline")
+                .unwrap()
+                .0,
+            Bump::Patch
+        );
+    }
+
+    #[test]
+    fn ignored() {
+        for message in [
+            "docs: readme",
+            "chore(deps): bump",
+            "Merge branch 'main'",
+            "fix:",
+            "fix:   ",
+            ": nothing",
+            "fix(scope: unclosed",
+            "fix bug: no colon type",
+            "",
+        ] {
+            assert!(parse(message).is_none(), "{message}");
+        }
+    }
+}
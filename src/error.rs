@@ -0,0 +1,275 @@
+//! Errors, with the file and position they come from.
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use std::{fmt, io, path, sync::Arc};
+use thiserror::Error;
+
+/// A result with a [`NanpaError`].
+pub type Result<T> = std::result::Result<T, NanpaError>;
+
+/// Errors returned by nanpa.
+#[derive(Debug, Error, Diagnostic)]
+pub enum NanpaError {
+    /// An invalid `.nanparc`.
+    #[error("{at}: {message}")]
+    #[diagnostic(code(nanpa::config))]
+    Config {
+        /// File and line of the error.
+        at: Location,
+        /// What went wrong.
+        message: String,
+        /// Contents of the file, to show the error in context.
+        #[source_code]
+        source_code: Arc<NamedSource>,
+        /// Position of the error in the file, if known.
+        #[label("here")]
+        span: Option<SourceSpan>,
+    },
+
+    /// An invalid changeset.
+    #[error("{at}: {message}")]
+    #[diagnostic(code(nanpa::changeset))]
+    Changeset {
+        /// File and line of the error.
+        at: Location,
+        /// What went wrong.
+        message: String,
+        /// Contents of the file, to show the error in context.
+        #[source_code]
+        source_code: Arc<NamedSource>,
+        /// Position of the error in the file, if known.
+        #[label("here")]
+        span: Option<SourceSpan>,
+    },
+
+    /// A version that can't be parsed or bumped.
+    #[error("{}{message}", prefix(file))]
+    #[diagnostic(code(nanpa::version))]
+    Version {
+        /// Config of the package, if known.
+        file: Option<path::PathBuf>,
+        /// What went wrong.
+        message: String,
+    },
+
+    /// A language manifest that can't be updated.
+    #[error("{}: {message}", file.display())]
+    #[diagnostic(code(nanpa::language))]
+    Language {
+        /// The manifest.
+        file: path::PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+
+    /// A changelog or changelog template that can't be read or written.
+    #[error("{}: {message}", file.display())]
+    #[diagnostic(code(nanpa::changelog))]
+    Changelog {
+        /// The changelog or template.
+        file: path::PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+
+    /// A failed git command.
+    #[error("{message}")]
+    #[diagnostic(code(nanpa::git))]
+    Git {
+        /// What went wrong, with git's output.
+        message: String,
+    },
+
+    /// A file that can't be read or written.
+    #[error("{}: {source}", file.display())]
+    #[diagnostic(code(nanpa::io))]
+    Io {
+        /// The file.
+        file: path::PathBuf,
+        /// The underlying error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// A command that can't run as invoked.
+    #[error("{message}")]
+    #[diagnostic(code(nanpa::usage))]
+    Usage {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+/// A file, and optionally a line in it.
+#[derive(Debug)]
+pub struct Location {
+    /// The file.
+    pub file: path::PathBuf,
+    /// Line in the file, 1-based.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}", self.file.display()),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+impl NanpaError {
+    /// A command that can't run as invoked.
+    pub fn usage(message: impl ToString) -> Self {
+        Self::Usage {
+            message: message.to_string(),
+        }
+    }
+
+    /// A failed git command.
+    pub fn git(message: impl ToString) -> Self {
+        Self::Git {
+            message: message.to_string(),
+        }
+    }
+
+    /// A version error, not yet attached to a package.
+    pub fn version(message: impl ToString) -> Self {
+        Self::Version {
+            file: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Attach the config of the package a version error is about.
+    pub fn for_package(self, config: &path::Path) -> Self {
+        match self {
+            Self::Version {
+                file: None,
+                message,
+            } => Self::Version {
+                file: Some(config.to_path_buf()),
+                message,
+            },
+            error => error,
+        }
+    }
+
+    /// A language manifest that can't be updated.
+    pub fn language(file: &path::Path, message: impl ToString) -> Self {
+        Self::Language {
+            file: file.to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    /// A changelog or changelog template that can't be read or written.
+    pub fn changelog(file: &path::Path, message: impl ToString) -> Self {
+        Self::Changelog {
+            file: file.to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    /// An error in a whole changeset file, without a position.
+    pub fn changeset(file: &path::Path, message: impl ToString) -> Self {
+        Self::Changeset {
+            at: Location {
+                file: file.to_path_buf(),
+                line: None,
+            },
+            message: message.to_string(),
+            source_code: named(file, ""),
+            span: None,
+        }
+    }
+
+    /// An error at `span` in a changeset with contents `source`.
+    pub fn changeset_at(
+        file: &path::Path,
+        source: &str,
+        span: SourceSpan,
+        message: impl ToString,
+    ) -> Self {
+        Self::Changeset {
+            at: Location {
+                file: file.to_path_buf(),
+                line: Some(line_of(source, span.offset())),
+            },
+            message: message.to_string(),
+            source_code: named(file, source),
+            span: Some(span),
+        }
+    }
+
+    /// An error in a whole config file, without a position.
+    pub fn config(file: &path::Path, message: impl ToString) -> Self {
+        Self::Config {
+            at: Location {
+                file: file.to_path_buf(),
+                line: None,
+            },
+            message: message.to_string(),
+            source_code: named(file, ""),
+            span: None,
+        }
+    }
+
+    /// An error at `span` in a config with contents `source`.
+    pub fn config_at(
+        file: &path::Path,
+        source: &str,
+        span: SourceSpan,
+        message: impl ToString,
+    ) -> Self {
+        Self::Config {
+            at: Location {
+                file: file.to_path_buf(),
+                line: Some(line_of(source, span.offset())),
+            },
+            message: message.to_string(),
+            source_code: named(file, source),
+            span: Some(span),
+        }
+    }
+}
+
+impl From<glob::GlobError> for NanpaError {
+    fn from(error: glob::GlobError) -> Self {
+        Self::Io {
+            file: error.path().to_path_buf(),
+            source: error.into(),
+        }
+    }
+}
+
+/// Attach a file to IO errors.
+pub trait IoContext<T> {
+    /// Turn an IO error into a [`NanpaError::Io`] about `file`.
+    fn at(self, file: impl AsRef<path::Path>) -> Result<T>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn at(self, file: impl AsRef<path::Path>) -> Result<T> {
+        self.map_err(|source| NanpaError::Io {
+            file: file.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+fn prefix(file: &Option<path::PathBuf>) -> String {
+    match file {
+        Some(file) => format!("{}: ", file.display()),
+        None => String::new(),
+    }
+}
+
+// shared, to keep errors small
+fn named(file: &path::Path, source: &str) -> Arc<NamedSource> {
+    Arc::new(NamedSource::new(file.to_string_lossy(), source.to_string()))
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
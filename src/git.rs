@@ -0,0 +1,177 @@
+use crate::error::{IoContext, NanpaError, Result};
+use std::{fs, path, process};
+
+#[derive(Clone)]
+pub struct Addition {
+    pub hash: String,
+    pub author: String,
+}
+
+pub struct Commit {
+    pub hash: String,
+    pub message: String,
+    // absolute paths
+    pub files: Vec<path::PathBuf>,
+}
+
+fn git(dir: &path::Path, args: &[&str]) -> Result<String> {
+    git_paths(dir, args, &[])
+}
+
+/// Run git with `args`, followed by `--` and `paths` if there are any.
+fn git_paths(dir: &path::Path, args: &[&str], paths: &[&path::Path]) -> Result<String> {
+    let mut command = process::Command::new("git");
+    command
+        .current_dir(dir)
+        .args(["-c", "core.quotepath=off"])
+        .args(args);
+    if !paths.is_empty() {
+        command.arg("--").args(paths);
+    }
+    let output = command.stdin(process::Stdio::null()).output().at(dir)?;
+    if !output.status.success() {
+        return Err(NanpaError::git(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub fn toplevel(dir: &path::Path) -> Result<path::PathBuf> {
+    let toplevel = git(dir, &["rev-parse", "--show-toplevel"])?;
+    fs::canonicalize(toplevel.trim()).at(toplevel.trim())
+}
+
+/// List non-merge commits in `range`, oldest first.
+pub fn log(dir: &path::Path, range: &str) -> Result<Vec<Commit>> {
+    let toplevel = toplevel(dir)?;
+    let log = git(
+        dir,
+        &[
+            "log",
+            "--no-merges",
+            "--reverse",
+            "--format=%x1e%H%x1f%B%x1f",
+            "--name-only",
+            range,
+        ],
+    )?;
+
+    let mut commits = vec![];
+    for record in log.split('\x1e').skip(1) {
+        let mut fields = record.splitn(3, '\x1f');
+        let (Some(hash), Some(message), Some(files)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(NanpaError::git("could not parse git log output"));
+        };
+        commits.push(Commit {
+            hash: hash.to_string(),
+            message: message.trim().to_string(),
+            files: files
+                .lines()
+                .filter(|file| !file.is_empty())
+                .map(|file| toplevel.join(file))
+                .collect(),
+        });
+    }
+
+    Ok(commits)
+}
+
+/// Find the most recent commit that added `file`, if any.
+pub fn added_by(dir: &path::Path, file: &path::Path) -> Result<Option<Addition>> {
+    let log = git_paths(
+        dir,
+        &[
+            "log",
+            "--diff-filter=A",
+            "--max-count=1",
+            "--format=%H%x1f%an",
+        ],
+        &[file],
+    )?;
+
+    Ok(log
+        .trim()
+        .split_once('\x1f')
+        .map(|(hash, author)| Addition {
+            hash: hash.to_string(),
+            author: author.to_string(),
+        }))
+}
+
+/// List files changed in the working tree since `since`, including untracked
+/// files, as absolute paths.
+pub fn changed_files(dir: &path::Path, since: &str) -> Result<Vec<path::PathBuf>> {
+    let toplevel = toplevel(dir)?;
+    let diff = git(dir, &["diff", "--name-only", since, "--"])?;
+    let untracked = git(
+        dir,
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    )?;
+
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|file| !file.is_empty())
+        .map(|file| toplevel.join(file))
+        .collect())
+}
+
+/// Commit exactly `files` (absolute paths, possibly deleted) with `message`.
+pub fn commit(dir: &path::Path, files: &[path::PathBuf], message: &str) -> Result<()> {
+    let (existing, deleted): (Vec<_>, Vec<_>) = files.iter().partition(|file| file.exists());
+    let mut paths: Vec<&path::Path> = existing.iter().map(|file| file.as_path()).collect();
+
+    if !existing.is_empty() {
+        git_paths(dir, &["add"], &paths)?;
+    }
+    if !deleted.is_empty() {
+        // changeset files that were never committed are unknown to git
+        let deleted_paths: Vec<&path::Path> = deleted.iter().map(|file| file.as_path()).collect();
+        let tracked = git_paths(
+            dir,
+            &["ls-files", "--cached", "--full-name"],
+            &deleted_paths,
+        )?;
+        let toplevel = toplevel(dir)?;
+        for file in deleted {
+            let Ok(relative) = file.strip_prefix(&toplevel) else {
+                return Err(NanpaError::git(format!(
+                    "{} is outside the repository",
+                    file.display()
+                )));
+            };
+            let relative = relative.to_string_lossy();
+            if tracked.lines().any(|line| line == relative) {
+                git_paths(dir, &["rm", "--cached", "--quiet"], &[file])?;
+                paths.push(file);
+            }
+        }
+    }
+
+    git_paths(dir, &["commit", "--quiet", "--message", message], &paths)?;
+
+    Ok(())
+}
+
+/// Create an annotated tag on `HEAD`.
+pub fn tag(dir: &path::Path, name: &str, message: &str) -> Result<()> {
+    git(
+        dir,
+        &[
+            "tag",
+            "--annotate",
+            "--cleanup=whitespace",
+            name,
+            "--message",
+            message,
+        ],
+    )?;
+
+    Ok(())
+}
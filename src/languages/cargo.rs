@@ -1,33 +1,66 @@
-use crate::nanpa::find_root;
-use anyhow::Result;
+use crate::error::{IoContext, NanpaError, Result};
+use crate::package;
 use glob::glob;
-use std::{env, fs, io::Write, path};
+use std::{fs, io::Write, path};
 use toml_edit::{value, DocumentMut};
 
-pub fn bump(version: String, location: path::PathBuf) -> Result<()> {
+pub fn bump(
+    version: String,
+    location: path::PathBuf,
+    dependents: bool,
+) -> Result<Vec<path::PathBuf>> {
     // update package version
-    let toml = fs::read_to_string(location.join("Cargo.toml"))?;
-    let mut doc = toml.parse::<DocumentMut>()?;
+    let manifest = location.join("Cargo.toml");
+    let toml = fs::read_to_string(&manifest).at(&manifest)?;
+    let mut doc = toml
+        .parse::<DocumentMut>()
+        .map_err(|e| NanpaError::language(&manifest, e))?;
     doc["package"]["version"] = value(version.clone());
-    let name = doc["package"]["name"].as_str().unwrap();
+    let Some(name) = doc["package"]["name"].as_str() else {
+        return Err(NanpaError::language(
+            &manifest,
+            "package.name must be a string",
+        ));
+    };
     let mut f = fs::OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(location.join("Cargo.toml"))?;
-    f.write_all(doc.to_string().as_bytes())?;
-    f.flush()?;
+        .open(&manifest)
+        .at(&manifest)?;
+    f.write_all(doc.to_string().as_bytes()).at(&manifest)?;
+    f.flush().at(&manifest)?;
+    let mut modified = vec![manifest.clone()];
+    if !dependents {
+        return Ok(modified);
+    }
 
     // update dependent packages' manifests
-    goto_highest_root()?;
-    for file in glob("**/.nanparc")? {
+    let root = highest_root(&location)?;
+    let Some(root) = root.to_str() else {
+        return Err(NanpaError::language(
+            &manifest,
+            format!("{} is not valid UTF-8", root.display()),
+        ));
+    };
+    let root = glob::Pattern::escape(root);
+    let configs =
+        glob(&format!("{root}/**/.nanparc*")).map_err(|e| NanpaError::language(&manifest, e))?;
+    for file in configs {
         let file = file?;
+        // one config per package
+        if package::config(file.parent().unwrap()) != Some(file.clone()) {
+            continue;
+        }
         let mut needs_change = false;
-        if let Ok(toml) = fs::read_to_string(file.parent().unwrap().join("Cargo.toml").clone()) {
-            let mut doc = toml.parse::<DocumentMut>()?;
+        let manifest = file.parent().unwrap().join("Cargo.toml");
+        if let Ok(toml) = fs::read_to_string(&manifest) {
+            let mut doc = toml
+                .parse::<DocumentMut>()
+                .map_err(|e| NanpaError::language(&manifest, e))?;
             if let Some(deps) = doc.get("dependencies") {
                 if let Some(dep) = deps.get(name) {
-                    if let Some(_) = dep.get("version") {
+                    if dep.get("version").is_some() {
                         needs_change = true;
                         doc["dependencies"][name]["version"] = value(version.clone())
                     }
@@ -36,7 +69,7 @@ pub fn bump(version: String, location: path::PathBuf) -> Result<()> {
             if let Some(workspace) = doc.get("workspace") {
                 if let Some(deps) = workspace.get("dependencies") {
                     if let Some(dep) = deps.get(name) {
-                        if let Some(_) = dep.get("version") {
+                        if dep.get("version").is_some() {
                             needs_change = true;
                             doc["workspace"]["dependencies"][name]["version"] =
                                 value(version.clone())
@@ -49,26 +82,26 @@ pub fn bump(version: String, location: path::PathBuf) -> Result<()> {
                     .create(true)
                     .write(true)
                     .truncate(true)
-                    .open(file.parent().unwrap().join("Cargo.toml"))?;
-                f.write_all(doc.to_string().as_bytes())?;
-                f.flush()?;
+                    .open(&manifest)
+                    .at(&manifest)?;
+                f.write_all(doc.to_string().as_bytes()).at(&manifest)?;
+                f.flush().at(&manifest)?;
+                modified.push(fs::canonicalize(&manifest).at(&manifest)?);
             }
         }
     }
 
-    Ok(())
+    Ok(modified)
 }
 
-fn goto_highest_root() -> Result<()> {
-    let cwd = env::current_dir()?;
-    if let Some(parent) = env::current_dir()?.parent() {
-        env::set_current_dir(parent)?;
-    }
-    if let Some(root) = find_root(false) {
-        let root = fs::canonicalize(root).unwrap();
-        env::set_current_dir(root)?;
-        return goto_highest_root();
-    }
-    env::set_current_dir(cwd)?;
-    Ok(())
+/// Find the highest directory above `location` with a `.nanparc`, or
+/// `location` itself if there is none.
+fn highest_root(location: &path::Path) -> Result<path::PathBuf> {
+    let location = fs::canonicalize(location).at(location)?;
+    Ok(location
+        .ancestors()
+        .filter(|dir| package::config(dir).is_some())
+        .last()
+        .unwrap_or(&location)
+        .to_path_buf())
 }
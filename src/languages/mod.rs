@@ -1,16 +1,25 @@
 use std::path;
 
-use anyhow::{bail, Result};
+use crate::error::{NanpaError, Result};
+use crate::package;
 
 mod cargo;
 mod node;
 
-pub fn run_language(language: String, version: String, location: path::PathBuf) -> Result<()> {
+/// Bump the version in the language's manifests, and in the manifests of
+/// dependent packages if `dependents` is set, returning the modified files.
+pub fn run_language(
+    language: String,
+    version: String,
+    location: path::PathBuf,
+    dependents: bool,
+) -> Result<Vec<path::PathBuf>> {
     match language.as_str() {
-        "cargo" | "rust" => cargo::bump(version, location)?,
-        "node" | "javascript" | "js" | "typescript" | "ts" => node::bump(version, location)?,
-        unknown => bail!("unsupported language {unknown}. see `nanpa list-languages` for a list of supported languages"),
+        "cargo" | "rust" => cargo::bump(version, location, dependents),
+        "node" | "javascript" | "js" | "typescript" | "ts" => node::bump(version, location),
+        unknown => Err(NanpaError::language(
+            &package::config(&location).unwrap_or(location.join(".nanparc")),
+            format!("unsupported language {unknown}. see `nanpa list-languages` for a list of supported languages"),
+        )),
     }
-
-    Ok(())
 }
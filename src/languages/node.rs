@@ -1,10 +1,12 @@
-use anyhow::Result;
+use crate::error::{IoContext, NanpaError, Result};
 use serde_json::Value;
 use std::{fs, io::Write, path};
 
-pub fn bump(version: String, location: path::PathBuf) -> Result<()> {
-    let json = fs::read_to_string(location.join("package.json"))?;
-    let mut doc: Value = serde_json::from_str(json.as_str())?;
+pub fn bump(version: String, location: path::PathBuf) -> Result<Vec<path::PathBuf>> {
+    let file = location.join("package.json");
+    let json = fs::read_to_string(&file).at(&file)?;
+    let mut doc: Value =
+        serde_json::from_str(json.as_str()).map_err(|e| NanpaError::language(&file, e))?;
 
     if let Some(v) = doc.get_mut("version") {
         *v = Value::from(version);
@@ -14,9 +16,11 @@ pub fn bump(version: String, location: path::PathBuf) -> Result<()> {
         .create(true)
         .write(true)
         .truncate(true)
-        .open(location.join("package.json"))?;
-    f.write_all(serde_json::to_string_pretty(&doc)?.as_bytes())?;
-    f.flush()?;
+        .open(&file)
+        .at(&file)?;
+    let json = serde_json::to_string_pretty(&doc).map_err(|e| NanpaError::language(&file, e))?;
+    f.write_all(json.as_bytes()).at(&file)?;
+    f.flush().at(&file)?;
 
-    Ok(())
+    Ok(vec![file])
 }
@@ -0,0 +1,30 @@
+//! A language-agnostic release manager.
+//!
+//! [`Nanpa`] loads a tree of packages from `.nanparc` files. Pending changes
+//! are read from changesets in `.nanpa/*.kdl` with [`changeset::pending`],
+//! [`Nanpa::plan`] computes the next version of each package from them, and
+//! [`Nanpa::changesets`] writes the release: changelogs, versions in
+//! `.nanparc` and language manifests, and optionally a commit and tags.
+//!
+//! Version bumps for each scheme are in [`version`], and changelog sections are
+//! built with [`changelog::Changelog`].
+//!
+//! Errors are [`NanpaError`]s, which implement [`miette::Diagnostic`] so
+//! mistakes in `.nanparc` files and changesets can be shown in context.
+
+#![warn(missing_docs)]
+
+pub mod changelog;
+pub mod changeset;
+mod conventional;
+pub mod error;
+mod git;
+mod languages;
+mod nanpa;
+pub mod package;
+mod template;
+pub mod version;
+
+pub use error::NanpaError;
+pub use nanpa::{Bumped, ChangesetOptions, Changesets, Filter, FromCommits, Nanpa, Plan, Release};
+pub use package::Package;
@@ -1,12 +1,9 @@
 mod cli;
-mod languages;
-mod nanpa;
-mod package;
-
-use colored::Colorize;
 
 fn main() {
     if let Err(e) = cli::command() {
-        eprintln!("{} {}", "error:".red().bold(), e);
+        // config and changeset errors render with the offending line
+        eprintln!("{:?}", miette::Report::new(e));
+        std::process::exit(1);
     };
 }
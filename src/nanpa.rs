@@ -1,41 +1,225 @@
-use crate::cli::SemverVersionAdd;
-use crate::package;
-use crate::{cli::SemverVersion, languages};
-use anyhow::{bail, Result};
-use chrono;
-use glob::glob;
-use kdl::{KdlDocument, KdlNode};
+use crate::changelog::{self, feed, Config, Format};
+use crate::changeset::{self, Pending};
+use crate::error::{IoContext, NanpaError, Result};
+use crate::package::{self, PACKAGE_OPTIONS};
+use crate::version::{self, Bump};
+use crate::{conventional, git, languages};
+use kdl::{KdlDocument, KdlEntry, KdlNode};
 use rand::prelude::IndexedRandom;
-use semver;
-use std::{
-    collections, env, fs,
-    io::{self, BufRead, Read, Write},
-    path, process,
-};
+use std::{collections, env, fs, path, process};
 
+/// A tree of packages, loaded from the `.nanparc` in the current directory or
+/// the nearest parent directory.
+///
+/// Methods taking a `package` path resolve it against the root, unless it is
+/// absolute.
 pub struct Nanpa {
+    // directory of the root config, canonical
+    root: path::PathBuf,
     // only packages with versions
     packages: Vec<package::Package>,
+    // display name of the root package
+    name: String,
+    // release settings from the root config
+    commit_message: Option<String>,
+    check_ignore: Vec<String>,
+    // aggregated changelog for every release, absolute
+    root_changelog: Option<path::PathBuf>,
+    // directories matched by `packages` without a config, and the config
+    // that matched them
+    skipped: Vec<(path::PathBuf, path::PathBuf)>,
+    config: Config,
+}
+
+/// A package released by `nanpa changeset`.
+#[non_exhaustive]
+pub struct Release {
+    /// The package, as it was before the release.
+    pub package: package::Package,
+    /// New version.
+    pub version: String,
+    /// Changelog section of the release, in markdown.
+    pub changelog: String,
+    /// Files modified by the release, absolute.
+    pub files: Vec<path::PathBuf>,
+}
+
+/// A release computed from pending changesets, before anything is written.
+#[non_exhaustive]
+pub struct Plan {
+    /// The package to release.
+    pub package: package::Package,
+    /// Version the package would be released as.
+    pub version: String,
+    /// The package's pending changesets.
+    pub pending: Pending,
+}
+
+/// A package version changed by `nanpa bump`.
+#[non_exhaustive]
+pub struct Bumped {
+    /// The package, as it was before the bump.
+    pub package: package::Package,
+    /// New version.
+    pub version: String,
+}
+
+/// What a `nanpa changeset` run did.
+#[derive(Default)]
+#[non_exhaustive]
+pub struct Changesets {
+    /// Released packages, by location.
+    pub releases: Vec<Release>,
+    /// Packages without pending changesets.
+    pub unchanged: Vec<package::Package>,
+    /// Packages whose changelog was emptied in the editor, so weren't released.
+    pub aborted: Vec<package::Package>,
+    /// Aggregated changelog, if it was updated.
+    pub root_changelog: Option<path::PathBuf>,
+    /// Message of the release commit, if one was made.
+    pub commit: Option<String>,
+    /// Tags created for the release.
+    pub tags: Vec<String>,
+}
+
+/// Changesets written by `nanpa add --from-commits`.
+#[derive(Default)]
+#[non_exhaustive]
+pub struct FromCommits {
+    /// Commit hashes, each with its changeset file, relative to the root.
+    pub written: Vec<(String, path::PathBuf)>,
+    /// Commit hashes whose change type isn't declared, with that type.
+    pub undeclared: Vec<(String, String)>,
+}
+
+/// A package's options as `keyword value` lines, each with the config file it
+/// was set in.
+type Options = Vec<(String, String)>;
+
+/// What happened to a package in a `nanpa changeset` run.
+enum Outcome {
+    Released(Box<Release>),
+    Unchanged,
+    Aborted,
+}
+
+/// Packages chosen by name, path or changes, for commands that take several.
+#[derive(Default)]
+pub struct Filter {
+    /// Choose every package.
+    pub all: bool,
+    /// Package names, or globs of package paths relative to the root.
+    pub patterns: Vec<String>,
+    /// Choose packages with files changed since this git ref.
+    pub changed_since: Option<String>,
+}
+
+/// How `nanpa changeset` releases packages.
+#[derive(Default)]
+pub struct ChangesetOptions {
+    /// Release as a prerelease of this kind, e.g. `alpha`.
+    pub pre: Option<String>,
+    /// Write the changelog without opening `$EDITOR`.
+    pub yes: bool,
+    /// Commit the files modified by the release.
+    pub commit: bool,
+    /// Tag each released package. Only with `commit`.
+    pub tag: bool,
+}
+
+impl Filter {
+    fn is_empty(&self) -> bool {
+        !self.all && self.patterns.is_empty() && self.changed_since.is_none()
+    }
 }
 
 impl Nanpa {
+    /// Load the package tree from the current directory.
     pub fn new() -> Result<Self> {
+        let dir = env::current_dir().at(".")?;
+        Self::load(&dir)
+    }
+
+    /// Load the package tree from the `.nanparc` in `dir` or the nearest
+    /// parent directory.
+    pub fn load(dir: &path::Path) -> Result<Self> {
+        let root = match find_root(dir) {
+            Some(path) => package::Package::get(fs::canonicalize(&path).at(&path)?)?,
+            None => {
+                return Err(NanpaError::usage(format!(
+                    "could not find .nanparc file in {} or its parents",
+                    dir.display()
+                )));
+            }
+        };
+
+        if root.root_changelog.is_some() && root.version.is_some() {
+            return Err(NanpaError::config(
+                &root.config,
+                "root-changelog requires a config with packages",
+            ));
+        }
+
+        let skipped = root.skipped();
+        let packages = root.clone().flatten()?;
+        let tag_pattern = root.tag_pattern.clone().unwrap_or(
+            if packages.len() == 1 {
+                "v{version}"
+            } else {
+                "{name}@{version}"
+            }
+            .to_string(),
+        );
+
         Ok(Self {
-            packages: match find_root(true) {
-                Some(path) => package::Package::get(path)?.flatten()?,
-                None => {
-                    bail!("could not find .nanparc file");
-                }
+            root: root.location.clone(),
+            name: root.display_name(),
+            commit_message: root.commit_message.clone(),
+            check_ignore: root.check_ignore.clone(),
+            root_changelog: root
+                .root_changelog
+                .as_ref()
+                .map(|file| root.location.join(file)),
+            skipped,
+            config: Config {
+                types: root.change_types.clone(),
+                commits: root.changelog_commits,
+                commit_url: root.commit_url.clone(),
+                pr_url: root.pr_url.clone(),
+                issue_url: root.issue_url.clone(),
+                repository: root.repository.clone(),
+                tag_pattern,
+                template: match &root.changelog_template {
+                    Some(file) => {
+                        let file = root.location.join(file);
+                        let template = fs::read_to_string(&file).at(&file)?;
+                        Some((file, template))
+                    }
+                    None => None,
+                },
             },
+            packages,
         })
     }
 
+    /// Changelog settings from the root config.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Directories matched by `packages` that were left out for having no
+    /// config, with the config that matched them.
+    pub fn skipped(&self) -> &[(path::PathBuf, path::PathBuf)] {
+        &self.skipped
+    }
+
+    /// Packages with a version, by location.
     pub fn packages(&self) -> collections::HashMap<String, package::Package> {
         let mut packages = collections::HashMap::new();
 
         for package in self.packages.clone() {
             packages.insert(
-                package.location.to_str().unwrap().to_string(),
+                package.location.to_string_lossy().to_string(),
                 package.clone(),
             );
         }
@@ -43,519 +227,850 @@ impl Nanpa {
         packages
     }
 
-    pub fn bump_semver(&self, version: &SemverVersion, package: Option<String>) -> Result<()> {
+    /// Bump packages' versions by hand, according to their version schemes.
+    pub fn bump(
+        &self,
+        version: &version::Manual,
+        package: Option<String>,
+        filter: &Filter,
+    ) -> Result<Vec<Bumped>> {
+        let mut bumped = vec![];
+        for package in self.filter(package, filter)? {
+            let version = write_manual(package.clone(), version)?;
+            bumped.push(Bumped { package, version });
+        }
+
+        Ok(bumped)
+    }
+
+    /// Set packages' versions.
+    pub fn bump_custom(
+        &self,
+        version: String,
+        package: Option<String>,
+        filter: &Filter,
+    ) -> Result<Vec<Bumped>> {
+        let mut bumped = vec![];
+        for package in self.filter(package, filter)? {
+            write_custom(package.clone(), version.clone())?;
+            bumped.push(Bumped {
+                package,
+                version: version.clone(),
+            });
+        }
+
+        Ok(bumped)
+    }
+
+    /// Packages targeted by a command that needs them chosen explicitly: the
+    /// given package, the root package, or the packages matching `filter`.
+    fn filter(&self, package: Option<String>, filter: &Filter) -> Result<Vec<package::Package>> {
         if let Some(path) = package {
-            let path = path::PathBuf::from(path);
-            let path = fs::canonicalize(&path).unwrap();
-            if let Some(package) = self.packages().get(path.to_str().unwrap()).cloned() {
-                write_semver(package, version)?;
-            } else {
-                bail!("could not find package");
+            if !filter.is_empty() {
+                return Err(NanpaError::usage(
+                    "a package can't be combined with a filter",
+                ));
             }
-        } else if self.packages.len() == 1 && self.packages[0].location == find_root(false).unwrap()
-        {
-            write_semver(
-                self.packages()
-                    .get(find_root(false).unwrap().to_str().unwrap())
-                    .unwrap()
-                    .clone(),
-                version,
-            )?;
-        } else {
-            bail!("no package specified and more than one package in tree");
+            return Ok(vec![self.find(&path)?]);
+        }
+        if filter.is_empty() {
+            if self.packages.len() == 1 && self.packages[0].location == self.root {
+                return Ok(vec![self.packages[0].clone()]);
+            }
+            return Err(NanpaError::usage(
+                "no package specified and more than one package in tree",
+            ));
         }
 
-        Ok(())
+        let mut patterns = vec![];
+        for pattern in &filter.patterns {
+            patterns.push(
+                glob::Pattern::new(pattern)
+                    .map_err(|e| NanpaError::usage(format!("invalid filter {pattern}: {e}")))?,
+            );
+        }
+        let changed = match &filter.changed_since {
+            Some(since) => {
+                let locations = self.locations()?;
+                let mut changed = vec![];
+                for file in git::changed_files(&self.root, since)? {
+                    if let Some(owner) = owner(&locations, &file) {
+                        changed.push(owner.clone());
+                    }
+                }
+                Some((locations, changed))
+            }
+            None => None,
+        };
+
+        let mut packages = vec![];
+        for (i, package) in self.packages.iter().enumerate() {
+            let relative = package
+                .location
+                .strip_prefix(&self.root)
+                .unwrap_or(&package.location);
+            let matches = patterns.is_empty()
+                || patterns.iter().any(|pattern| {
+                    pattern.matches(&package.display_name()) || pattern.matches_path(relative)
+                });
+            let changed = match &changed {
+                Some((locations, changed)) => changed.contains(&locations[i]),
+                None => true,
+            };
+            if matches && changed {
+                packages.push(package.clone());
+            }
+        }
+        packages.sort_by(|a, b| a.location.cmp(&b.location));
+
+        if packages.is_empty() {
+            return Err(NanpaError::usage("no packages match the filter"));
+        }
+        Ok(packages)
     }
 
-    pub fn bump_custom(&self, version: String, package: Option<String>) -> Result<()> {
+    /// The package at `path`, relative to the root.
+    fn find(&self, path: &str) -> Result<package::Package> {
+        let target = self.root.join(path);
+        let location = fs::canonicalize(&target).at(&target)?;
+        self.packages
+            .iter()
+            .find(|package| package.location == location)
+            .cloned()
+            .ok_or(NanpaError::usage(format!("could not find package {path}")))
+    }
+
+    /// Packages targeted by a command: the given package, the root package,
+    /// or every package in the tree.
+    fn select(&self, package: Option<String>) -> Result<Vec<package::Package>> {
         if let Some(path) = package {
-            let path = path::PathBuf::from(path);
-            let path = fs::canonicalize(&path).unwrap();
-            if let Some(package) = self.packages().get(path.to_str().unwrap()).cloned() {
-                write_custom(package, version.clone())?;
-            } else {
-                bail!("could not find package");
-            }
-        } else if self.packages.len() == 1 && self.packages[0].location == find_root(false).unwrap()
-        {
-            write_custom(
-                self.packages()
-                    .get(find_root(false).unwrap().to_str().unwrap())
-                    .unwrap()
-                    .clone(),
-                version.clone(),
-            )?;
+            Ok(vec![self.find(&path)?])
+        } else if self.packages.len() == 1 && self.packages[0].location == self.root {
+            Ok(vec![self.packages[0].clone()])
         } else {
-            bail!("no package specified and more than one package in tree");
+            let mut packages: Vec<_> = self.packages().into_values().collect();
+            packages.sort_by(|a, b| a.location.cmp(&b.location));
+            Ok(packages)
         }
+    }
 
-        println!(
-            "{}: {} -> {}",
-            self.packages[0].location.to_str().unwrap(),
-            self.packages[0].version.clone().unwrap(),
-            version,
-        );
+    /// Compute the next release of each selected package from its pending
+    /// changesets, without writing anything. Packages without changes are
+    /// left out.
+    pub fn plan(&self, package: Option<String>, pre: Option<String>) -> Result<Vec<Plan>> {
+        let root = self.root.clone();
+        let mut plans = vec![];
 
-        Ok(())
+        for package in self.select(package)? {
+            plans.extend(plan(package, &root, pre.clone(), self.config.clone())?);
+        }
+
+        Ok(plans)
     }
 
+    /// Release each selected package from its pending changesets, updating
+    /// changelogs and versions, then optionally commit and tag the release.
     pub fn changesets(
         &self,
         package: Option<String>,
-        pre: Option<String>,
-        yes: bool,
-    ) -> Result<()> {
-        if let Some(path) = package {
-            let path = path::PathBuf::from(path);
-            let path = fs::canonicalize(&path).unwrap();
-            if let Some(package) = self.packages().get(path.to_str().unwrap()).cloned() {
-                changesets(package, find_root(false).unwrap(), pre, yes)?;
-            } else {
-                bail!("could not find package");
+        options: &ChangesetOptions,
+    ) -> Result<Changesets> {
+        let root = self.root.clone();
+        let mut report = Changesets::default();
+
+        for package in self.select(package)? {
+            match changesets(
+                package.clone(),
+                root.clone(),
+                options.pre.clone(),
+                options.yes,
+                self.config.clone(),
+            )? {
+                Outcome::Released(release) => report.releases.push(*release),
+                Outcome::Unchanged => report.unchanged.push(package),
+                Outcome::Aborted => report.aborted.push(package),
             }
-        } else if self.packages.len() == 1 && self.packages[0].location == find_root(false).unwrap()
-        {
-            changesets(
-                self.packages()
-                    .get(find_root(false).unwrap().to_str().unwrap())
-                    .unwrap()
-                    .clone(),
-                find_root(false).unwrap(),
-                pre,
-                yes,
-            )?
-        } else {
-            let root = fs::canonicalize(find_root(false).unwrap()).unwrap();
-            for package in self.packages().values() {
-                changesets(package.clone(), root.clone(), pre.clone(), yes)?;
+        }
+        let releases = &report.releases;
+
+        let mut files = vec![];
+        if let (Some(file), false) = (&self.root_changelog, releases.is_empty()) {
+            let mut sections: Vec<(String, changelog::Release)> = vec![];
+            for release in releases {
+                let title = format!("{} {}", release.package.display_name(), release.version);
+                let notes = changelog::Document::parse(&release.changelog).releases;
+                if notes.is_empty() {
+                    // a release with its own heading style, without its heading
+                    let text = release.changelog.trim_start();
+                    let text = text.split_once('\n').map_or("", |(_, text)| text.trim());
+                    sections.push((
+                        title.clone(),
+                        changelog::Release {
+                            text: text.to_string(),
+                            ..changelog::Release::new(release.version.clone(), None)
+                        },
+                    ));
+                } else {
+                    for notes in notes {
+                        sections.push((title.clone(), notes));
+                    }
+                }
             }
+            sections.sort_by(|(a, _), (b, _)| a.cmp(b));
+            changelog::aggregate(file, &sections)?;
+            files.push(fs::canonicalize(file).at(file)?);
+            report.root_changelog = Some(file.clone());
         }
 
-        Ok(())
+        if options.commit && !releases.is_empty() {
+            let (message, tags) = self.commit(&root, releases, files, options.tag)?;
+            report.commit = Some(message);
+            report.tags = tags;
+        }
+
+        Ok(report)
     }
 
-    pub fn add(
+    /// Pending changes of each selected package as an `Unreleased` section,
+    /// also written to the changelog if `write` is true.
+    pub fn changelog(
         &self,
         package: Option<String>,
-        bump: SemverVersionAdd,
-        change_type: Option<String>,
-        message: Option<String>,
-    ) -> Result<()> {
-        add_changeset(
-            package,
-            find_root(false).unwrap(),
-            bump,
-            change_type,
-            message,
-        )?;
-
-        Ok(())
-    }
-}
+        write: bool,
+    ) -> Result<Vec<(package::Package, changelog::Release)>> {
+        let root = self.root.clone();
+        let mut sections = vec![];
 
-fn write_semver(package: package::Package, version: &SemverVersion) -> Result<String> {
-    let file = match fs::File::open(package.location.join(".nanparc")) {
-        Ok(file) => io::BufReader::new(file),
-        Err(e) => {
-            bail!("{}", e.to_string());
+        for package in self.select(package)? {
+            let release = unreleased(package.clone(), root.clone(), self.config.clone(), write)?;
+            sections.push((package, release));
         }
-    };
 
-    if let Ok(mut parsed) = semver::Version::parse(package.version.clone().unwrap().as_str()) {
-        match version {
-            SemverVersion::Major => {
-                parsed.major += 1;
-                parsed.minor = 0;
-                parsed.patch = 0;
-                parsed.pre = semver::Prerelease::new("").unwrap();
-            }
-            SemverVersion::Minor => {
-                parsed.minor += 1;
-                parsed.patch = 0;
-                parsed.pre = semver::Prerelease::new("").unwrap();
-            }
-            SemverVersion::Patch => {
-                parsed.patch += 1;
-                parsed.pre = semver::Prerelease::new("").unwrap();
-            }
-            SemverVersion::Prerelease(x) => {
-                parsed.pre = semver::Prerelease::new(x.version.as_str()).unwrap()
+        Ok(sections)
+    }
+
+    /// The changelog section for a release.
+    pub fn notes(
+        &self,
+        package: Option<String>,
+        version: Option<String>,
+        plain: bool,
+        no_heading: bool,
+    ) -> Result<String> {
+        let package = match self.select(package)?.as_slice() {
+            [package] => package.clone(),
+            _ => {
+                return Err(NanpaError::usage(
+                    "no package specified and more than one package in tree",
+                ))
             }
         };
 
-        let mut lines = vec![];
-        for line in file.lines() {
-            if let Ok(line) = line {
-                if line.starts_with("version") {
-                    lines.push("version ".to_string() + parsed.to_string().as_str())
-                } else {
-                    lines.push(line.clone())
+        let Some(file) = &package.changelog else {
+            return Err(NanpaError::config(
+                &package.config,
+                "package has no changelog",
+            ));
+        };
+        let document = changelog::Document::read(file)?;
+        let Some(release) = document.release(version.as_deref()) else {
+            match version {
+                Some(version) => {
+                    return Err(NanpaError::changelog(file, format!("no release {version}")))
                 }
+                None => return Err(NanpaError::changelog(file, "no releases")),
             }
+        };
+
+        let notes = if no_heading {
+            release.body()
+        } else {
+            release.to_string()
+        };
+        if plain {
+            Ok(changelog::plain(notes.trim()))
+        } else {
+            Ok(notes.trim().to_string())
         }
+    }
 
-        let mut f = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(package.location.join(".nanparc"))?;
-        f.write_all((lines.join("\n") + "\n").as_bytes())?;
-        f.flush()?;
+    /// The resolved config of each selected package, by location relative to
+    /// the root: each option as a `keyword value` line, with the config file
+    /// it was set in.
+    pub fn resolved_config(&self, package: Option<String>) -> Result<Vec<(String, Options)>> {
+        let root = package::Package::get(self.root.clone())?;
+        let mut packages = self.select(package)?;
+        packages.sort_by(|a, b| a.location.cmp(&b.location));
+        let relative = |file: &path::Path| {
+            file.strip_prefix(&self.root)
+                .unwrap_or(file)
+                .display()
+                .to_string()
+        };
 
-        println!(
-            "{}: {} -> {}",
-            package.location.to_str().unwrap(),
-            package.version.clone().unwrap(),
-            parsed.to_string()
-        );
+        let mut configs = vec![];
+        for package in &packages {
+            let mut options: Vec<(String, String)> = vec![];
+            for (keyword, value) in package.options() {
+                if !PACKAGE_OPTIONS.contains(&keyword) {
+                    continue;
+                }
+                let source = match package.source(keyword) {
+                    Some(file) if file == package.config => relative(file),
+                    Some(file) => format!("inherited from {}", relative(file)),
+                    None => "default".to_string(),
+                };
+                options.push((format!("{keyword} {value}"), source));
+            }
+            for (keyword, value) in root.options() {
+                if PACKAGE_OPTIONS.contains(&keyword) {
+                    continue;
+                }
+                let source = match root.source(keyword) {
+                    Some(file) => relative(file),
+                    None => "default".to_string(),
+                };
+                options.push((format!("{keyword} {value}"), source));
+            }
+            if root.tag_pattern.is_none() {
+                options.push((
+                    format!("tag {}", self.config.tag_pattern),
+                    "default".to_string(),
+                ));
+            }
 
-        run_language(package.clone(), parsed.to_string())?;
-        run_custom(package, parsed.to_string())?;
+            let location = match relative(&package.location).as_str() {
+                "" => ".".to_string(),
+                location => location.to_string(),
+            };
+            configs.push((location, options));
+        }
 
-        Ok(parsed.to_string())
-    } else {
-        bail!("package version is not a valid semver version");
+        Ok(configs)
     }
-}
 
-fn write_custom(package: package::Package, version: String) -> Result<()> {
-    let file = match fs::File::open(package.location.join(".nanparc")) {
-        Ok(file) => io::BufReader::new(file),
-        Err(e) => {
-            bail!("{}", e.to_string());
-        }
-    };
+    /// Write an Atom feed of releases from the changelogs, returning the feed
+    /// file and the releases left out for having no date, by changelog file.
+    pub fn feed(
+        &self,
+        package: Option<String>,
+        output: Option<String>,
+    ) -> Result<(path::PathBuf, Vec<(path::PathBuf, String)>)> {
+        let root = self.root.clone();
+        let mut packages = self.select(package)?;
+        packages.sort_by(|a, b| a.location.cmp(&b.location));
+        let repository = self
+            .config
+            .repository
+            .as_ref()
+            .map(|repository| repository.trim_end_matches('/').to_string());
 
-    let mut lines = vec![];
-    for line in file.lines() {
-        if let Ok(line) = line {
-            if line.starts_with("version") {
-                lines.push("version ".to_string() + version.as_str())
-            } else {
-                lines.push(line.clone())
+        let mut items = vec![];
+        let mut skipped = vec![];
+        for package in &packages {
+            let Some(file) = &package.changelog else {
+                continue;
+            };
+            let document = changelog::Document::read(file)?;
+            for release in document.releases {
+                if release.is_unreleased() {
+                    continue;
+                }
+                if !feed::has_date(&release) {
+                    skipped.push((file.clone(), release.version));
+                    continue;
+                }
+
+                let tag = package.fill_template(&self.config.tag_pattern, &release.version);
+                let link = repository
+                    .as_ref()
+                    .filter(|_| !package.private)
+                    .map(|repository| format!("{repository}/releases/tag/{tag}"));
+                items.push(feed::Item {
+                    title: format!("{} {}", package.display_name(), release.version),
+                    id: link.clone().unwrap_or(urn(&tag)),
+                    link,
+                    release,
+                });
             }
         }
+
+        let (title, location) = match packages.as_slice() {
+            [package] => (package.display_name(), package.location.clone()),
+            _ => (self.name.clone(), root),
+        };
+        let output = match output {
+            Some(output) => path::PathBuf::from(output),
+            None => location.join("releases.xml"),
+        };
+        fs::write(
+            &output,
+            feed::atom(&title, &urn(&title), repository.as_deref(), items),
+        )
+        .at(&output)?;
+
+        Ok((output, skipped))
     }
 
-    let mut f = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(package.location.join(".nanparc"))?;
-    f.write_all((lines.join("\n") + "\n").as_bytes())?;
-    f.flush()?;
+    /// Commit a release, and tag it if `tag` is set, returning the commit
+    /// message and the tags created.
+    fn commit(
+        &self,
+        root: &path::Path,
+        releases: &[Release],
+        mut files: Vec<path::PathBuf>,
+        tag: bool,
+    ) -> Result<(String, Vec<String>)> {
+        for release in releases {
+            for file in &release.files {
+                if !files.contains(file) {
+                    files.push(file.clone());
+                }
+            }
+        }
 
-    run_language(package.clone(), version.clone())?;
-    run_custom(package, version)?;
-    Ok(())
-}
+        let tags: Vec<Option<String>> = releases
+            .iter()
+            .map(|release| {
+                // private packages are never published, so never tagged
+                (!release.package.private).then(|| {
+                    release
+                        .package
+                        .fill_template(&self.config.tag_pattern, &release.version)
+                })
+            })
+            .collect();
+        let names: Vec<String> = releases
+            .iter()
+            .map(|release| format!("{}@{}", release.package.display_name(), release.version))
+            .collect();
+        let message = self
+            .commit_message
+            .clone()
+            .unwrap_or("release {releases}".to_string())
+            .replace("{releases}", names.join(", ").as_str());
+        let message = match releases {
+            [release] => release.package.fill_template(&message, &release.version),
+            _ => message,
+        };
 
-pub fn new() -> Result<Nanpa> {
-    Nanpa::new()
-}
+        git::commit(root, &files, &message)?;
 
-pub fn find_root(stdout: bool) -> Option<path::PathBuf> {
-    let mut dir = env::current_dir().unwrap();
-    if dir.join(".nanparc").exists() {
-        Some(dir)
-    } else {
-        if stdout {
-            eprintln!("current directory does not contain .nanparc, searching up");
-        }
-        loop {
-            if dir == path::PathBuf::from("/") {
-                return None;
-            }
-            dir.pop();
-            if dir.join(".nanparc").exists() {
-                return Some(dir);
+        let mut created = vec![];
+        if tag {
+            for (release, name) in releases.iter().zip(tags) {
+                let Some(name) = name else {
+                    continue;
+                };
+                git::tag(root, &name, release.changelog.trim())?;
+                created.push(name);
             }
         }
-    }
-}
 
-fn run_custom(package: package::Package, parsed: String) -> Result<()> {
-    if let Some(custom) = package.custom {
-        env::set_var("VERSION", parsed);
-        env::set_current_dir(package.location.clone())?;
-        process::Command::new(package.location.join(custom)).spawn()?;
+        Ok((message, created))
     }
 
-    Ok(())
-}
+    /// Write a changeset, opening it in `$EDITOR`. Returns the changeset file,
+    /// or `None` if it was left empty and removed.
+    pub fn add(
+        &self,
+        package: Option<String>,
+        bump: Bump,
+        change_type: Option<String>,
+        message: Option<String>,
+    ) -> Result<Option<path::PathBuf>> {
+        add_changeset(package, self.root.clone(), bump, change_type, message)
+    }
 
-fn changesets(
-    package: package::Package,
-    root: path::PathBuf,
-    pre: Option<String>,
-    yes: bool,
-) -> Result<()> {
-    let mut bump = 0;
-    let mut changelog = Changelog::new();
-    let mut to_delete: Vec<path::PathBuf> = vec![];
-
-    env::set_current_dir(root)?;
-    for file in glob(".nanpa/*.kdl")? {
-        let file = file?;
-        let mut changeset: KdlDocument = fs::read_to_string(file.clone())?.parse()?;
-        let nodes = changeset.nodes_mut();
-        let nodes_cloned = nodes.clone();
-        let package_nodes: Vec<&KdlNode> = nodes_cloned
+    fn locations(&self) -> Result<Vec<path::PathBuf>> {
+        self.packages
             .iter()
-            .filter(|change| {
-                change.get("package").is_some_and(|path| {
-                    package.location == fs::canonicalize(path.value().as_string().unwrap()).unwrap()
-                })
-            })
-            .collect();
-        for node in package_nodes {
-            if node.get(0).cloned().is_some() {
-                match node.name().to_string().as_str() {
-                    "major" => {
-                        bump = 3;
-                        changelog.push(node.clone())?;
-                    }
-                    "minor" => {
-                        if bump < 2 {
-                            bump = 2;
-                        }
-                        changelog.push(node.clone())?;
-                    }
-                    "patch" => {
-                        if bump == 0 {
-                            bump = 1;
-                        }
-                        changelog.push(node.clone())?;
+            .map(|package| fs::canonicalize(&package.location).at(&package.location))
+            .collect()
+    }
+
+    /// Write changesets for the Conventional Commits in a git revision range.
+    /// Commits mapped to a change type that isn't declared are left out.
+    pub fn add_from_commits(&self, range: String) -> Result<FromCommits> {
+        let root = self.root.clone();
+        let locations = self.locations()?;
+
+        let mut report = FromCommits::default();
+        for commit in git::log(&root, &range)? {
+            let Some(change) = conventional::parse(&commit.message) else {
+                continue;
+            };
+            if !self
+                .config
+                .types
+                .iter()
+                .any(|change_type| change_type.name == change.change_type)
+            {
+                report
+                    .undeclared
+                    .push((commit.hash, change.change_type.to_string()));
+                continue;
+            }
+
+            // attribute the change to the deepest package owning each file
+            let mut owners: Vec<&path::PathBuf> = vec![];
+            for file in &commit.files {
+                if let Some(owner) = owner(&locations, file) {
+                    if !owners.contains(&owner) {
+                        owners.push(owner);
                     }
-                    unknown => bail!("{}: unknown keyword {unknown}", file.to_str().unwrap()),
                 }
             }
+            if owners.is_empty() {
+                continue;
+            }
 
-            nodes.remove(nodes.iter().position(|x| *x == *node).unwrap());
-        }
-        if changeset.nodes().is_empty() {
-            to_delete.push(fs::canonicalize(file.to_str().unwrap().to_string()).unwrap())
-        } else {
-            let mut f = fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(file)?;
-            f.write_all(changeset.to_string().as_bytes())?;
-            f.flush()?;
-        }
-    }
-
-    env::set_current_dir(package.location.clone())?;
-    for file in glob(".nanpa/*.kdl")? {
-        let file = file?;
-        let changeset: KdlDocument = fs::read_to_string(file.clone())?.parse()?;
-        for node in changeset.nodes() {
-            if node.get(0).cloned().is_some() {
-                match node.name().to_string().as_str() {
-                    "major" => {
-                        bump = 3;
-                        changelog.push(node.clone())?;
-                    }
-                    "minor" => {
-                        if bump < 2 {
-                            bump = 2;
-                        }
-                        changelog.push(node.clone())?;
-                    }
-                    "patch" => {
-                        if bump == 0 {
-                            bump = 1;
-                        }
-                        changelog.push(node.clone())?;
-                    }
-                    unknown => bail!("unknown keyword {unknown}"),
+            let mut changeset = KdlDocument::new();
+            for owner in owners {
+                let mut node = KdlNode::new(change.bump.to_string());
+                if *owner != root {
+                    node.push(KdlEntry::new_prop(
+                        "package",
+                        owner
+                            .strip_prefix(&root)
+                            .unwrap_or(owner)
+                            .to_string_lossy()
+                            .as_ref(),
+                    ));
                 }
+                node.push(KdlEntry::new_prop("type", change.change_type));
+                node.push(KdlEntry::new(change.description.clone()));
+                changeset.nodes_mut().push(node);
             }
+            changeset.fmt();
+
+            let mut fpath = root.join(".nanpa");
+            _ = fs::create_dir(fpath.clone());
+            fpath.push(format!("{}.kdl", gen_changeset_name()));
+            fs::write(&fpath, changeset.to_string()).at(&fpath)?;
+            report.written.push((
+                commit.hash,
+                fpath.strip_prefix(&root).unwrap_or(&fpath).to_path_buf(),
+            ));
         }
-        to_delete.push(fs::canonicalize(file.to_str().unwrap().to_string()).unwrap());
-    }
 
-    let semver = semver::Version::parse(package.version.clone().unwrap().as_str());
-    if semver.is_err() {
-        bail!(
-            "{}: package version is not a valid semver version",
-            package.location.to_str().unwrap()
-        );
+        Ok(report)
     }
-    let mut version = semver.unwrap();
-    match bump {
-        0 => {
-            if !version.pre.is_empty() {
-                if let Some(pre) = pre {
-                    let head = version.pre.split_once(".").unwrap().0;
-                    if head != pre {
-                        version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                    } else {
-                        println!(
-                            "{}: no changesets found",
-                            package.location.to_str().unwrap()
-                        );
-                        return Ok(());
-                    }
-                } else {
-                    version.pre = semver::Prerelease::EMPTY;
+
+    /// Fail if a package changed since `since` has no changeset.
+    pub fn check(&self, since: String, ignore: Vec<String>) -> Result<()> {
+        let root = self.root.clone();
+        let locations = self.locations()?;
+
+        let mut patterns = vec![];
+        for pattern in self.check_ignore.iter().chain(ignore.iter()) {
+            patterns.push(glob::Pattern::new(pattern).map_err(|e| {
+                NanpaError::usage(format!("invalid check-ignore glob {pattern}: {e}"))
+            })?);
+        }
+
+        let mut changed: Vec<&path::PathBuf> = vec![];
+        let mut changesets: Vec<path::PathBuf> = vec![];
+        for file in git::changed_files(&root, &since)? {
+            let Ok(relative) = file.strip_prefix(&root) else {
+                continue;
+            };
+            if changeset::is_changeset(relative) {
+                if file.exists() {
+                    changesets.push(file);
                 }
-            } else {
-                println!(
-                    "{}: no changesets found",
-                    package.location.to_str().unwrap()
-                );
-                return Ok(());
+                continue;
             }
-        }
-        3 => {
-            if version.minor == 0 && version.patch == 0 && !version.pre.is_empty() && pre.is_some()
+            if patterns
+                .iter()
+                .any(|pattern| pattern.matches_path(relative))
             {
-                // XXX: assumes prerelease matches `^[\w\d]+\.\d+$`
-                let (head, tail) = version.pre.split_once(".").unwrap();
-                let pre = pre.unwrap();
-                if pre == head {
-                    let tail = tail.parse::<u8>().unwrap() + 1;
-                    version.pre =
-                        semver::Prerelease::new(format!("{head}.{tail}").as_str()).unwrap();
-                } else {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                }
-            } else {
-                version.major += 1;
-                version.minor = 0;
-                version.patch = 0;
-                if let Some(pre) = pre {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                } else {
-                    version.pre = semver::Prerelease::new("").unwrap();
+                continue;
+            }
+            if let Some(owner) = owner(&locations, &file) {
+                if !changed.contains(&owner) {
+                    changed.push(owner);
                 }
             }
         }
-        2 => {
-            if version.patch == 0 && !version.pre.is_empty() && pre.is_some() {
-                // XXX: assumes prerelease matches `^[\w\d]+\.\d+$`
-                let (head, tail) = version.pre.split_once(".").unwrap();
-                let pre = pre.unwrap();
-                if pre == head {
-                    let tail = tail.parse::<u8>().unwrap() + 1;
-                    version.pre =
-                        semver::Prerelease::new(format!("{head}.{tail}").as_str()).unwrap();
-                } else {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                }
-            } else {
-                version.minor += 1;
-                version.patch = 0;
-                if let Some(pre) = pre {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                } else {
-                    version.pre = semver::Prerelease::new("").unwrap();
-                }
+
+        let mut covered: Vec<path::PathBuf> = vec![];
+        for file in changesets {
+            let (source, changeset) = changeset::read(&file)?;
+            // .nanpa/ directory -> directory holding it
+            let dir = file.parent().and_then(path::Path::parent).unwrap_or(&root);
+            for node in changeset.nodes() {
+                let target = match node.get("package") {
+                    Some(package) => match package.value().as_string() {
+                        Some(target) => {
+                            let target = dir.join(target);
+                            fs::canonicalize(&target).at(&target)?
+                        }
+                        None => {
+                            return Err(NanpaError::changeset_at(
+                                &file,
+                                &source,
+                                *package.span(),
+                                "package must be a string",
+                            ))
+                        }
+                    },
+                    None => dir.to_path_buf(),
+                };
+                covered.push(target);
             }
         }
-        1 => {
-            if !version.pre.is_empty() && pre.is_some() {
-                // XXX: assumes prerelease matches `^[\w\d]+\.\d+$`
-                let (head, tail) = version.pre.split_once(".").unwrap();
-                let pre = pre.unwrap();
-                if pre == head {
-                    let tail = tail.parse::<u8>().unwrap() + 1;
-                    version.pre =
-                        semver::Prerelease::new(format!("{head}.{tail}").as_str()).unwrap();
-                } else {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
-                }
-            } else {
-                version.patch += 1;
-                if let Some(pre) = pre {
-                    version.pre = semver::Prerelease::new(format!("{pre}.0").as_str()).unwrap();
+
+        let missing: Vec<String> = changed
+            .into_iter()
+            .filter(|location| !covered.contains(location))
+            .map(|location| {
+                let relative = location.strip_prefix(&root).unwrap_or(location);
+                if relative.as_os_str().is_empty() {
+                    ".".to_string()
                 } else {
-                    version.pre = semver::Prerelease::new("").unwrap();
+                    relative.to_string_lossy().to_string()
                 }
-            }
+            })
+            .collect();
+        if !missing.is_empty() {
+            return Err(NanpaError::usage(format!(
+                "packages changed since {since} without a changeset: {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+fn write_manual(package: package::Package, manual: &version::Manual) -> Result<String> {
+    let current = package.version.clone().unwrap_or_default();
+    let parsed = version::bump(package.scheme, &current, manual)
+        .map_err(|e| e.for_package(&package.config))?;
+
+    write_custom(package, parsed.clone())?;
+
+    Ok(parsed)
+}
+
+fn write_custom(package: package::Package, version: String) -> Result<Vec<path::PathBuf>> {
+    package.write_version(&version)?;
+
+    let mut modified = vec![package.config.clone()];
+    modified.append(&mut run_language(package.clone(), version.clone())?);
+    run_custom(package, version)?;
+    Ok(modified)
+}
+
+/// A stable feed identifier for something without a URL.
+fn urn(name: &str) -> String {
+    format!(
+        "urn:nanpa:{}",
+        name.split_whitespace().collect::<Vec<_>>().join("-")
+    )
+}
+
+/// Find the nearest directory with a `.nanparc`, starting from `dir`.
+fn find_root(dir: &path::Path) -> Option<path::PathBuf> {
+    dir.ancestors()
+        .find(|dir| package::config(dir).is_some())
+        .map(path::Path::to_path_buf)
+}
+
+fn run_custom(package: package::Package, parsed: String) -> Result<()> {
+    if let Some(custom) = package.custom {
+        let script = package.location.join(custom);
+        process::Command::new(&script)
+            .current_dir(&package.location)
+            .env("VERSION", parsed)
+            .spawn()
+            .at(&script)?;
+    }
+
+    Ok(())
+}
+
+/// Pending changes for a package as an `Unreleased` section, also written to
+/// its changelog if `write` is true.
+fn unreleased(
+    package: package::Package,
+    root: path::PathBuf,
+    config: Config,
+    write: bool,
+) -> Result<changelog::Release> {
+    let pending = changeset::pending(&package, &root, config.clone())?;
+    let release = pending.changelog.release("Unreleased".to_string(), None);
+
+    if !write {
+        return Ok(release);
+    }
+
+    let Some(file) = &package.changelog else {
+        return Err(NanpaError::config(
+            &package.config,
+            "package has no changelog",
+        ));
+    };
+    let mut document = changelog::Document::read(file)?;
+    document.releases.retain(|release| !release.is_unreleased());
+    document.insert(release.clone());
+    config.compare(&mut document, &package);
+    write_changelog(file, &document)?;
+
+    Ok(release)
+}
+
+fn write_changelog(file: &path::Path, document: &changelog::Document) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).at(dir)?;
+    }
+    fs::write(file, document.to_string()).at(file)
+}
+
+fn plan(
+    package: package::Package,
+    root: &path::Path,
+    pre: Option<String>,
+    config: Config,
+) -> Result<Option<Plan>> {
+    let pending = changeset::pending(&package, root, config)?;
+
+    let current = package.version.clone().unwrap_or_default();
+    let next = match &package.next_version {
+        // nothing to release, don't let the script make one up
+        Some(_) if pending.bump == Bump::None && pre.is_none() => Ok(None),
+        Some(script) => {
+            version::next_script(script, &package.location, &current, pending.bump, pre)
         }
-        _ => bail!("something has gone horribly wrong"),
+        None => version::next(package.scheme, &current, pending.bump, pre),
     };
-    let version = version.to_string();
+    match next {
+        Ok(Some(version)) => Ok(Some(Plan {
+            package,
+            version,
+            pending,
+        })),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e.for_package(&package.config)),
+    }
+}
 
-    let mut markdown = changelog.markdown(version.clone());
+fn changesets(
+    package: package::Package,
+    root: path::PathBuf,
+    pre: Option<String>,
+    yes: bool,
+    config: Config,
+) -> Result<Outcome> {
+    let Some(Plan {
+        package,
+        version,
+        pending:
+            Pending {
+                changelog,
+                rewrites,
+                to_delete,
+                ..
+            },
+    }) = plan(package.clone(), &root, pre, config.clone())?
+    else {
+        return Ok(Outcome::Unchanged);
+    };
+    let mut modified: Vec<path::PathBuf> = vec![];
+
+    let mut markdown = changelog.markdown(version.clone(), &package)?;
     if !yes {
         if let Ok(editor) = env::var("EDITOR") {
             let mut tmpfile = env::temp_dir();
             tmpfile.push("CHANGESET_EDITMSG.md");
-            let mut buffer = fs::File::create(&tmpfile)?;
-            writeln!(buffer, "{}", markdown.trim())?;
-            let status = process::Command::new(editor).arg(&tmpfile).status()?;
-            markdown = "".to_string();
-            fs::File::open(tmpfile)?.read_to_string(&mut markdown)?;
+            fs::write(&tmpfile, markdown.trim().to_string() + "\n").at(&tmpfile)?;
+            let status = process::Command::new(&editor)
+                .arg(&tmpfile)
+                .status()
+                .at(&editor)?;
+            markdown = fs::read_to_string(&tmpfile).at(&tmpfile)?;
 
             if markdown.trim().is_empty() || !status.success() {
-                println!(
-                    "{}: no changelog found, aborting",
-                    package.location.to_str().unwrap()
-                );
-                return Ok(());
+                return Ok(Outcome::Aborted);
             }
         } else {
-            bail!("EDITOR must be set");
+            return Err(NanpaError::usage("EDITOR must be set"));
         }
     }
 
-    let changelog = fs::read_to_string(path::PathBuf::from("CHANGELOG.md"))
-        .unwrap_or("# Changelog\n\n".to_string());
-    let (prologue, changelog) = changelog
-        .split_once("##")
-        .unwrap_or((changelog.as_str(), ""));
-    let changelog = if changelog.is_empty() {
-        changelog.to_string()
-    } else {
-        "\n## ".to_string() + changelog.trim() + "\n"
-    };
-    let mut f = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("CHANGELOG.md")?;
-    f.write_all(prologue.as_bytes())?;
-    f.write_all((markdown.trim().to_string() + "\n").as_bytes())?;
-    f.write_all(changelog.as_bytes())?;
-    f.flush()?;
-
-    println!(
-        "{}: {} -> {}",
-        package.location.to_str().unwrap(),
-        package.version.clone().unwrap(),
-        version,
-    );
+    let mut releases = changelog::Document::parse(&markdown).releases;
+    let file = package.changelog.clone();
+    if let (Some(file), true) = (&file, package.changelog_formats.contains(&Format::Markdown)) {
+        let mut document = changelog::Document::read(file)?;
+        // the unreleased section becomes this release
+        let unreleased = document.take_unreleased();
+        for release in releases.iter_mut() {
+            if let Some(unreleased) = &unreleased {
+                release.merge(unreleased);
+            }
+            document.insert(release.clone());
+        }
+        config.compare(&mut document, &package);
+        if releases.is_empty() {
+            // no `## version` heading, e.g. from a template with its own
+            // heading style, so insert the release as written
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir).at(dir)?;
+            }
+            fs::write(file, document.render_with(&markdown)).at(file)?;
+        } else {
+            write_changelog(file, &document)?;
+        }
+        modified.push(fs::canonicalize(file).at(file)?);
+    }
+    if let Some(dir) = file.as_ref().and_then(|file| file.parent()) {
+        // other formats live next to the markdown changelog, and are written
+        // from the changesets rather than the edited release
+        for format in package
+            .changelog_formats
+            .iter()
+            .filter(|format| **format != Format::Markdown)
+        {
+            fs::create_dir_all(dir).at(dir)?;
+            modified.push(format.write(dir, &changelog, &version)?);
+        }
+    }
 
-    write_custom(package, version)?;
+    for (file, changeset) in rewrites {
+        fs::write(&file, changeset.to_string()).at(&file)?;
+        modified.push(file);
+    }
+
+    for file in write_custom(package.clone(), version.clone())? {
+        modified.push(fs::canonicalize(&file).at(&file)?);
+    }
 
     for file in to_delete {
-        fs::remove_file(file)?;
+        fs::remove_file(&file).at(&file)?;
+        modified.push(file);
     }
-    Ok(())
+    Ok(Outcome::Released(Box::new(Release {
+        package,
+        version,
+        changelog: markdown,
+        files: modified,
+    })))
 }
 
 fn add_changeset(
     package: Option<String>,
     mut fpath: path::PathBuf,
-    bump: SemverVersionAdd,
+    bump: Bump,
     change_type: Option<String>,
     message: Option<String>,
-) -> Result<()> {
+) -> Result<Option<path::PathBuf>> {
     if let Ok(editor) = env::var("EDITOR") {
         fpath.push(".nanpa");
         _ = fs::create_dir(fpath.clone());
         fpath.push(format!("{}.kdl", gen_changeset_name()));
-        let mut buffer = fs::File::create(&fpath)?;
 
-        let mut initial = match bump {
-            SemverVersionAdd::Major => "major ",
-            SemverVersionAdd::Minor => "minor ",
-            SemverVersionAdd::Patch => "patch ",
-        }
-        .to_string();
+        let mut initial = format!("{bump} ");
         if let Some(package) = package {
             initial += format!("package=\"{}\" ", package).as_str();
         }
@@ -566,129 +1081,42 @@ fn add_changeset(
         )
         .as_str();
 
-        writeln!(buffer, "{}", initial)?;
-        let status = process::Command::new(editor).arg(&fpath).status()?;
-        let mut done = "".to_string();
-        fs::File::open(fpath.clone())?.read_to_string(&mut done)?;
+        fs::write(&fpath, initial + "\n").at(&fpath)?;
+        let status = process::Command::new(&editor)
+            .arg(&fpath)
+            .status()
+            .at(&editor)?;
+        let done = fs::read_to_string(&fpath).at(&fpath)?;
 
         if done.trim().is_empty() || !status.success() {
-            println!("empty changeset, aborting",);
-            fs::remove_file(fpath)?;
-            return Ok(());
+            fs::remove_file(&fpath).at(&fpath)?;
+            return Ok(None);
         }
     } else {
-        bail!("EDITOR must be set");
+        return Err(NanpaError::usage("EDITOR must be set"));
     }
 
-    Ok(())
-}
-
-struct Changelog {
-    pub added: Vec<String>,
-    pub changed: Vec<String>,
-    pub deprecated: Vec<String>,
-    pub removed: Vec<String>,
-    pub fixed: Vec<String>,
-    pub security: Vec<String>,
+    Ok(Some(fpath))
 }
 
-impl Changelog {
-    pub fn new() -> Self {
-        Self {
-            added: vec![],
-            changed: vec![],
-            deprecated: vec![],
-            removed: vec![],
-            fixed: vec![],
-            security: vec![],
-        }
-    }
-
-    pub fn push(&mut self, change: KdlNode) -> Result<()> {
-        if let Some(change_type) = change.get("type") {
-            let change = change
-                .get(0)
-                .unwrap()
-                .value()
-                .as_string()
-                .unwrap()
-                .to_string();
-            match change_type.value().as_string().unwrap() {
-                "added" => self.added.push(change),
-                "changed" => self.changed.push(change),
-                "deprecated" => self.deprecated.push(change),
-                "removed" => self.removed.push(change),
-                "fixed" => self.fixed.push(change),
-                "security" => self.security.push(change),
-                unknown => bail!("unknown change type {unknown}"),
-            };
-        } else {
-            bail!("change type (added, changed, etc.) must be specified")
-        }
-
-        Ok(())
-    }
-
-    pub fn markdown(&mut self, version: String) -> String {
-        let mut ret = format!(
-            "## [{}] - {}\n\n",
+fn run_language(package: package::Package, version: String) -> Result<Vec<path::PathBuf>> {
+    match package.language {
+        Some(language) => languages::run_language(
+            language,
             version,
-            chrono::Utc::now().format("%Y-%m-%d")
-        );
-
-        if !self.added.is_empty() {
-            ret += "### Added\n\n";
-            for item in self.added.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-        if !self.changed.is_empty() {
-            ret += "### Changed\n\n";
-            for item in self.changed.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-        if !self.deprecated.is_empty() {
-            ret += "### Deprecated\n\n";
-            for item in self.deprecated.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-        if !self.removed.is_empty() {
-            ret += "### Removed\n\n";
-            for item in self.removed.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-        if !self.fixed.is_empty() {
-            ret += "### Fixed\n\n";
-            for item in self.fixed.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-        if !self.security.is_empty() {
-            ret += "### Security\n\n";
-            for item in self.security.clone() {
-                ret += format!("- {item}\n").as_str();
-            }
-            ret += "\n";
-        }
-
-        ret
+            package.location.clone(),
+            !package.private,
+        ),
+        None => Ok(vec![]),
     }
 }
 
-fn run_language(package: package::Package, version: String) -> Result<()> {
-    if let Some(language) = package.language {
-        languages::run_language(language, version, package.location.clone())?;
-    }
-
-    Ok(())
+/// Find the deepest package location containing `file`.
+fn owner<'a>(locations: &'a [path::PathBuf], file: &path::Path) -> Option<&'a path::PathBuf> {
+    locations
+        .iter()
+        .filter(|location| file.starts_with(location))
+        .max_by_key(|location| location.components().count())
 }
 
 fn gen_changeset_name() -> String {
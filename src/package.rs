@@ -1,112 +1,542 @@
-use anyhow::{bail, Result};
+//! Packages, loaded from `.nanparc` files.
+
+use crate::changelog::{ChangeType, Format};
+use crate::error::{IoContext, NanpaError, Result};
+use crate::version::Scheme;
 use glob::glob;
-use std::{
-    fs,
-    io::{self, BufRead},
-    path,
-};
+use kdl::{KdlDocument, KdlNode};
+use miette::SourceSpan;
+use std::{fs, ops, path};
+
+/// Config file names, in order of precedence.
+const CONFIGS: [&str; 2] = [".nanparc.kdl", ".nanparc"];
+
+/// Options read from each package's own config. The rest are only read from
+/// the root, apart from `packages` and `exclude`.
+pub const PACKAGE_OPTIONS: [&str; 9] = [
+    "version",
+    "name",
+    "language",
+    "custom",
+    "next-version",
+    "scheme",
+    "changelog",
+    "changelog-format",
+    "private",
+];
+
+/// Options a superpackage passes down to the packages under it.
+const INHERITED: [&str; 7] = [
+    "language",
+    "custom",
+    "next-version",
+    "scheme",
+    "changelog",
+    "changelog-format",
+    "private",
+];
+
+/// Options whose values add up when repeated, instead of being set once.
+const REPEATABLE: [&str; 5] = [
+    "packages",
+    "exclude",
+    "check-ignore",
+    "changelog-format",
+    "type",
+];
 
+/// A package or superpackage, loaded from its `.nanparc`.
 #[derive(Clone)]
 pub struct Package {
     // only used for package traversal, flattened
     subpackages: Vec<Package>,
+    // directories matched by `packages` without a config
+    skipped: Vec<path::PathBuf>,
+    // config file each option was set in, by keyword
+    sources: Vec<(String, path::PathBuf)>,
+    /// Current version, unset for superpackages.
     pub version: Option<String>,
+    /// Language whose manifests get the new version.
     pub language: Option<String>,
+    /// Script run with `$VERSION` after each bump, relative to the package.
     pub custom: Option<String>,
+    /// Script computing the next version, relative to the package.
+    pub next_version: Option<String>,
+    /// Package directory.
     pub location: path::PathBuf,
+    /// Config file the package was read from.
+    pub config: path::PathBuf,
+    /// Name for tags and commit messages, defaults to the directory name.
     pub name: Option<String>,
+    /// Versioning scheme.
+    pub scheme: Scheme,
+    /// Release commit message template. Root only.
+    pub commit_message: Option<String>,
+    /// Tag name template. Root only.
+    pub tag_pattern: Option<String>,
+    /// Globs of files ignored by `nanpa check`. Root only.
+    pub check_ignore: Vec<String>,
+    /// Whether changelog entries show the commit and author that added their
+    /// changeset. Root only.
+    pub changelog_commits: bool,
+    /// Released, but never tagged or bumped in dependents.
+    pub private: bool,
+    /// Link template for commits. Root only.
+    pub commit_url: Option<String>,
+    /// Link template for pull requests. Root only.
+    pub pr_url: Option<String>,
+    /// Link template for issues. Root only.
+    pub issue_url: Option<String>,
+    /// Repository URL for comparison links. Root only.
+    pub repository: Option<String>,
+    /// Change types declared with `type`, in order. Root only.
+    pub change_types: Vec<ChangeType>,
+    /// Changelog template file, relative to the root. Root only.
+    pub changelog_template: Option<String>,
+    /// Changelog formats to write.
+    pub changelog_formats: Vec<Format>,
+    /// Aggregated changelog, relative to the root. Root only.
+    pub root_changelog: Option<String>,
+    /// Changelog file, unset if the package has none.
+    pub changelog: Option<path::PathBuf>,
+}
+
+/// An option and its values, from either config format.
+#[derive(Clone)]
+struct Statement {
+    keyword: String,
+    values: Vec<String>,
+    span: SourceSpan,
+    // config file the option was set in
+    from: path::PathBuf,
 }
 
 impl Package {
+    /// Load the package in `path`, and the packages under it.
     pub fn get(path: path::PathBuf) -> Result<Self> {
-        let config = match fs::File::open(path.join(".nanparc")) {
-            Ok(file) => io::BufReader::new(file),
-            Err(e) => bail!(e),
+        Self::load(path, &[], true)
+    }
+
+    /// Load a package, with options inherited from its superpackages.
+    fn load(path: path::PathBuf, inherited: &[Statement], root: bool) -> Result<Self> {
+        let file = config(&path).unwrap_or(path.join(".nanparc"));
+        let source = fs::read_to_string(&file).at(&file)?;
+        let error = |span: SourceSpan, message: String| {
+            NanpaError::config_at(&file, &source, span, message)
         };
+        let mut statements = if is_kdl(&file, &source) {
+            kdl_statements(&file, &source)?
+        } else {
+            line_statements(&file, &source)
+        };
+
+        // own options override inherited ones, and are passed down in turn
+        let overridden = |keyword: &str| {
+            statements
+                .iter()
+                .any(|statement| statement.keyword == keyword)
+        };
+        let inherited: Vec<Statement> = inherited
+            .iter()
+            .filter(|statement| !overridden(&statement.keyword))
+            .cloned()
+            .collect();
+        let mut defaults = inherited.clone();
+        for statement in &statements {
+            if !INHERITED.contains(&statement.keyword.as_str()) || statement.values.is_empty() {
+                continue;
+            }
+            let mut statement = statement.clone();
+            // scripts stay relative to the package that set them
+            if matches!(statement.keyword.as_str(), "custom" | "next-version") {
+                statement.values[0] = path
+                    .join(&statement.values[0])
+                    .to_string_lossy()
+                    .to_string();
+            }
+            defaults.push(statement);
+        }
+        statements.extend(inherited);
 
         let mut subpackages = vec![];
+        let mut skipped = vec![];
         let mut version: Option<String> = None;
         let mut language: Option<String> = None;
         let mut custom: Option<String> = None;
+        let mut next_version: Option<String> = None;
         let mut name: Option<String> = None;
+        let mut scheme = Scheme::default();
+        let mut commit_message: Option<String> = None;
+        let mut tag_pattern: Option<String> = None;
+        let mut check_ignore: Vec<String> = vec![];
+        let mut changelog_commits = false;
+        let mut private = false;
+        let mut commit_url: Option<String> = None;
+        let mut pr_url: Option<String> = None;
+        let mut issue_url: Option<String> = None;
+        let mut repository: Option<String> = None;
+        let mut change_types: Vec<ChangeType> = vec![];
+        let mut changelog_template: Option<String> = None;
+        let mut changelog_formats: Vec<Format> = vec![];
+        let mut root_changelog: Option<String> = None;
+        let mut changelog = Some(path.join("CHANGELOG.md"));
 
-        for line in config.lines() {
-            if let Ok(line) = line {
-                if line.is_empty() || line.starts_with("#") {
-                    continue;
-                }
+        let mut version_span = SourceSpan::from(0);
+        let mut globs: Vec<(String, SourceSpan)> = vec![];
+        let mut excludes: Vec<(String, SourceSpan)> = vec![];
+        let mut seen: Vec<String> = vec![];
+        let mut sources: Vec<(String, path::PathBuf)> = vec![];
 
-                let words = line.split_whitespace().collect::<Vec<&str>>();
-                let (&keyword, rest) = words.split_first().unwrap();
-                if rest.len() == 0 {
-                    bail!("keyword {keyword} requires an argument");
-                }
-                match keyword {
-                    "packages" => {
-                        for &subpackage in rest {
-                            for entry in glob(subpackage)? {
-                                subpackages.push(Package::get(path.join(entry?))?)
-                            }
+        for Statement {
+            keyword,
+            values: rest,
+            span,
+            from,
+        } in statements
+        {
+            if rest.is_empty() {
+                return Err(error(
+                    span,
+                    format!("keyword {keyword} requires an argument"),
+                ));
+            }
+            if !REPEATABLE.contains(&keyword.as_str()) && seen.contains(&keyword) {
+                return Err(error(span, format!("{keyword} specified more than once")));
+            }
+            seen.push(keyword.clone());
+            if !sources.iter().any(|(set, _)| *set == keyword) {
+                sources.push((keyword.clone(), from));
+            }
+
+            match keyword.as_str() {
+                "packages" => {
+                    for subpackage in rest {
+                        match subpackage.strip_prefix('!') {
+                            Some(exclude) => excludes.push((exclude.to_string(), span)),
+                            None => globs.push((subpackage, span)),
                         }
                     }
-                    "version" => {
-                        version = Some(rest[0].to_string());
-                    }
-                    "language" => {
-                        language = Some(rest[0].to_string());
-                    }
-                    "custom" => {
-                        custom = Some(rest[0].to_string());
-                    }
-                    "name" => {
-                        name = Some(rest[0].to_string());
+                }
+                "exclude" => {
+                    excludes.extend(rest.into_iter().map(|exclude| (exclude, span)));
+                }
+                "version" => {
+                    version = Some(rest[0].to_string());
+                    version_span = span;
+                }
+                "language" => {
+                    language = Some(rest[0].to_string());
+                }
+                "custom" => {
+                    custom = Some(rest[0].to_string());
+                }
+                "next-version" => {
+                    next_version = Some(rest[0].to_string());
+                }
+                "name" => {
+                    name = Some(rest[0].to_string());
+                }
+                "scheme" => {
+                    scheme = Scheme::parse(&rest[0]).map_err(|e| error(span, e.to_string()))?;
+                }
+                "commit-message" => {
+                    commit_message = Some(rest.join(" "));
+                }
+                "tag" => {
+                    tag_pattern = Some(rest[0].to_string());
+                }
+                "changelog-commits" => {
+                    changelog_commits = boolean(&rest[0]).ok_or_else(|| {
+                        error(
+                            span,
+                            format!("changelog-commits must be true or false, got {}", rest[0]),
+                        )
+                    })?;
+                }
+                "private" => {
+                    private = boolean(&rest[0]).ok_or_else(|| {
+                        error(
+                            span,
+                            format!("private must be true or false, got {}", rest[0]),
+                        )
+                    })?;
+                }
+                "commit-url" => {
+                    commit_url = Some(rest[0].to_string());
+                }
+                "pr-url" => {
+                    pr_url = Some(rest[0].to_string());
+                }
+                "issue-url" => {
+                    issue_url = Some(rest[0].to_string());
+                }
+                "repository" => {
+                    repository = Some(rest[0].to_string());
+                }
+                "changelog-template" => {
+                    changelog_template = Some(rest[0].to_string());
+                }
+                "changelog-format" => {
+                    for format in &rest {
+                        changelog_formats
+                            .push(Format::parse(format).map_err(|e| error(span, e.to_string()))?);
                     }
-                    unknown => {
-                        bail!("unknown keyword {unknown}")
+                }
+                "changelog" => {
+                    changelog = match rest[0].as_str() {
+                        "none" => None,
+                        file => Some(path.join(file)),
+                    };
+                }
+                "root-changelog" => {
+                    root_changelog = Some(rest[0].to_string());
+                }
+                "type" => {
+                    let words: Vec<&str> = rest.iter().map(String::as_str).collect();
+                    let change_type = ChangeType::parse(&words);
+                    if change_types
+                        .iter()
+                        .any(|t: &ChangeType| t.name == change_type.name)
+                    {
+                        return Err(error(
+                            span,
+                            format!("type {} declared more than once", change_type.name),
+                        ));
                     }
+                    change_types.push(change_type);
                 }
+                "check-ignore" => {
+                    check_ignore.extend(rest);
+                }
+                unknown => return Err(error(span, format!("unknown keyword {unknown}"))),
+            }
+            if !root
+                && !PACKAGE_OPTIONS.contains(&keyword.as_str())
+                && !matches!(keyword.as_str(), "packages" | "exclude")
+            {
+                return Err(error(
+                    span,
+                    format!("{keyword} is only read from the root .nanparc"),
+                ));
             }
         }
 
-        if !subpackages.is_empty() {
-            if version.is_some() {
-                bail!(
-                    "{}: cannot specify version on superpackage",
-                    path.to_str().unwrap().to_string()
-                );
-            }
-            if language.is_some() {
-                bail!(
-                    "{}: cannot specify language on superpackage",
-                    path.to_str().unwrap().to_string()
-                );
+        // subpackages are loaded once every option they inherit is known
+        let dir = glob::Pattern::escape(&path.to_string_lossy());
+        let mut patterns = vec![];
+        for (exclude, span) in excludes {
+            patterns.push(
+                glob::Pattern::new(&format!("{dir}/{exclude}"))
+                    .map_err(|e| error(span, format!("invalid glob {exclude}: {e}")))?,
+            );
+        }
+        for (subpackage, span) in globs {
+            let entries = glob(&format!("{dir}/{subpackage}"))
+                .map_err(|e| error(span, format!("invalid glob {subpackage}: {e}")))?;
+            for entry in entries {
+                let entry = entry?;
+                if !entry.is_dir()
+                    || patterns.iter().any(|pattern| pattern.matches_path(&entry))
+                    || subpackages
+                        .iter()
+                        .any(|package: &Package| package.location == entry)
+                {
+                    continue;
+                }
+                if config(&entry).is_none() {
+                    skipped.push(entry);
+                    continue;
+                }
+                subpackages.push(Package::load(entry, &defaults, false)?)
             }
         }
 
+        if !subpackages.is_empty() && version.is_some() {
+            return Err(error(
+                version_span,
+                "cannot specify version on superpackage".to_string(),
+            ));
+        }
+
         if subpackages.is_empty() && version.is_none() {
-            bail!(
-                "{}: config must have version or packages",
-                path.to_str().unwrap().to_string()
-            );
+            return Err(NanpaError::config(
+                &file,
+                "config must have version or packages",
+            ));
         }
 
         if version.is_some() && language.is_none() && name.is_none() {
-            bail!(
-                "{}: packages without a supported language must have a name",
-                path.to_str().unwrap().to_string()
-            );
+            return Err(NanpaError::config(
+                &file,
+                "packages without a supported language must have a name",
+            ));
         }
 
         Ok(Self {
             subpackages,
+            skipped,
             version,
             language,
             custom,
+            next_version,
             location: path,
+            config: file,
             name,
+            scheme,
+            commit_message,
+            tag_pattern,
+            check_ignore,
+            changelog_commits,
+            private,
+            commit_url,
+            pr_url,
+            issue_url,
+            repository,
+            change_types: if change_types.is_empty() {
+                ChangeType::defaults()
+            } else {
+                change_types
+            },
+            changelog_template,
+            changelog_formats: if changelog_formats.is_empty() {
+                vec![Format::Markdown]
+            } else {
+                changelog_formats
+            },
+            root_changelog,
+            changelog,
+            sources,
         })
     }
 
+    /// Replace `{name}` and `{version}` in a tag or commit message template.
+    pub fn fill_template(&self, template: &str, version: &str) -> String {
+        template
+            .replace("{name}", self.display_name().as_str())
+            .replace("{version}", version)
+    }
+
+    /// Resolved options, as they would be written in `.nanparc`. Unset
+    /// options are left out.
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        let relative = |file: &path::Path| {
+            file.strip_prefix(&self.location)
+                .unwrap_or(file)
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let mut options = vec![];
+        let mut set = |keyword: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                options.push((keyword, value));
+            }
+        };
+        set("version", self.version.clone());
+        set("name", self.name.clone());
+        set("language", self.language.clone());
+        set("custom", self.custom.clone());
+        set("next-version", self.next_version.clone());
+        set("scheme", Some(self.scheme.to_string()));
+        set("private", Some(self.private.to_string()));
+        set(
+            "changelog",
+            Some(match &self.changelog {
+                Some(file) => relative(file),
+                None => "none".to_string(),
+            }),
+        );
+        set("commit-message", self.commit_message.clone());
+        set("tag", self.tag_pattern.clone());
+        set(
+            "changelog-commits",
+            Some(self.changelog_commits.to_string()),
+        );
+        set("commit-url", self.commit_url.clone());
+        set("pr-url", self.pr_url.clone());
+        set("issue-url", self.issue_url.clone());
+        set("repository", self.repository.clone());
+        set("changelog-template", self.changelog_template.clone());
+        let formats: Vec<&str> = self.changelog_formats.iter().map(Format::name).collect();
+        set("changelog-format", Some(formats.join(" ")));
+        set("root-changelog", self.root_changelog.clone());
+        if !self.check_ignore.is_empty() {
+            set("check-ignore", Some(self.check_ignore.join(" ")));
+        }
+        for change_type in &self.change_types {
+            let heading = change_type.heading.as_deref().unwrap_or("none");
+            set("type", Some(format!("{} {heading}", change_type.name)));
+        }
+
+        options
+    }
+
+    /// Config file an option was set in, or `None` if it has its default.
+    pub fn source(&self, keyword: &str) -> Option<&path::Path> {
+        self.sources
+            .iter()
+            .find(|(set, _)| set == keyword)
+            .map(|(_, file)| file.as_path())
+    }
+
+    /// Package name, falling back to the name of its directory.
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self
+                .location
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Set the version in the package's config, leaving the rest of it as is.
+    pub fn write_version(&self, version: &str) -> Result<()> {
+        let source = fs::read_to_string(&self.config).at(&self.config)?;
+
+        let config = if is_kdl(&self.config, &source) {
+            let mut document = parse_kdl(&self.config, &source)?;
+            if let Some(entry) = document
+                .get_mut("version")
+                .and_then(|node| node.entries_mut().first_mut())
+            {
+                entry.set_value(version);
+                entry.set_value_repr(entry.value().to_string());
+            }
+            document.to_string()
+        } else {
+            // line endings are kept with each line
+            source
+                .split_inclusive('\n')
+                .map(|line| match version_value(line) {
+                    Some(value) => {
+                        format!("{}{version}{}", &line[..value.start], &line[value.end..])
+                    }
+                    None => line.to_string(),
+                })
+                .collect()
+        };
+
+        fs::write(&self.config, config).at(&self.config)
+    }
+
+    /// Directories matched by `packages` in this config or the configs under
+    /// it that have no config of their own, with the config that matched them.
+    pub fn skipped(&self) -> Vec<(path::PathBuf, path::PathBuf)> {
+        let mut skipped: Vec<_> = self
+            .skipped
+            .iter()
+            .map(|dir| (self.config.clone(), dir.clone()))
+            .collect();
+        for package in &self.subpackages {
+            skipped.extend(package.skipped());
+        }
+
+        skipped
+    }
+
+    /// The packages with a version in this tree, this one included.
     pub fn flatten(self) -> Result<Vec<Self>> {
         let mut packages = vec![];
 
@@ -121,3 +551,397 @@ impl Package {
         Ok(packages)
     }
 }
+
+/// The config file of the package in `dir`, if it is one.
+pub fn config(dir: &path::Path) -> Option<path::PathBuf> {
+    CONFIGS
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|file| file.exists())
+}
+
+/// Whether a config is written in KDL: either named `.nanparc.kdl`, or a
+/// `.nanparc` that parses as KDL and uses quoted strings or blocks, which
+/// the line format never does.
+pub fn is_kdl(file: &path::Path, source: &str) -> bool {
+    file.extension().is_some_and(|extension| extension == "kdl")
+        || (source.contains(['"', '{']) && source.parse::<KdlDocument>().is_ok())
+}
+
+fn boolean(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Read the line format: one option per line, followed by its values.
+fn line_statements(file: &path::Path, source: &str) -> Vec<Statement> {
+    let mut statements = vec![];
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with("#") {
+            continue;
+        }
+        let mut words = line.split_whitespace().map(str::to_string);
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        statements.push(Statement {
+            keyword,
+            values: words.collect(),
+            span: (start, line.len()).into(),
+            from: file.to_path_buf(),
+        });
+    }
+    statements
+}
+
+/// Byte range of the value in a line format `version` statement.
+fn version_value(line: &str) -> Option<ops::Range<usize>> {
+    if line.starts_with("#") {
+        return None;
+    }
+    let mut words = line
+        .split_whitespace()
+        .map(|word| (word.as_ptr() as usize - line.as_ptr() as usize, word));
+    match (words.next(), words.next()) {
+        (Some((_, "version")), Some((start, value))) => Some(start..start + value.len()),
+        _ => None,
+    }
+}
+
+/// Read the KDL format: one node per option, with string or boolean
+/// arguments as values.
+fn kdl_statements(file: &path::Path, source: &str) -> Result<Vec<Statement>> {
+    let document = parse_kdl(file, source)?;
+
+    let mut statements = vec![];
+    flatten(file, source, document.nodes(), None, &mut statements)?;
+    Ok(statements)
+}
+
+fn parse_kdl(file: &path::Path, source: &str) -> Result<KdlDocument> {
+    source.parse().map_err(|e: kdl::KdlError| {
+        let kind = e.kind.to_string();
+        let kind = kind.trim_end_matches('.');
+        let message = match e.label {
+            Some(label) => format!("{label}: {kind}"),
+            None => kind.to_string(),
+        };
+        NanpaError::config_at(file, source, e.span, message)
+    })
+}
+
+/// Turn nodes into statements. Children of a block are prefixed with its
+/// name, so `changelog { format "rst" }` is `changelog-format rst`, except in
+/// `types`, where each child declares a type.
+fn flatten(
+    file: &path::Path,
+    source: &str,
+    nodes: &[KdlNode],
+    block: Option<&str>,
+    statements: &mut Vec<Statement>,
+) -> Result<()> {
+    for node in nodes {
+        let name = node.name().value();
+        let span = *node.name().span();
+        let (keyword, mut values) = match block {
+            Some("types") => ("type".to_string(), vec![name.to_string()]),
+            Some(block) => (format!("{block}-{name}"), vec![]),
+            None => (name.to_string(), vec![]),
+        };
+
+        for entry in node.entries() {
+            if entry.name().is_some() {
+                return Err(NanpaError::config_at(
+                    file,
+                    source,
+                    *entry.span(),
+                    format!("{keyword} does not take properties"),
+                ));
+            }
+            match (entry.value().as_string(), entry.value().as_bool()) {
+                (Some(value), _) => values.push(value.to_string()),
+                (_, Some(value)) => values.push(value.to_string()),
+                _ => {
+                    return Err(NanpaError::config_at(
+                        file,
+                        source,
+                        *entry.span(),
+                        format!("{keyword} values must be strings"),
+                    ))
+                }
+            }
+        }
+
+        match node.children() {
+            Some(children) => {
+                // a block may also set the option it's named after
+                if !values.is_empty() {
+                    statements.push(Statement {
+                        keyword: keyword.clone(),
+                        values,
+                        span,
+                        from: file.to_path_buf(),
+                    });
+                }
+                flatten(file, source, children.nodes(), Some(&keyword), statements)?;
+            }
+            None => statements.push(Statement {
+                keyword,
+                values,
+                span,
+                from: file.to_path_buf(),
+            }),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `files` to a fresh directory under the system temp directory.
+    fn tree(name: &str, files: &[(&str, &str)]) -> path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("nanpa-{}-{name}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        for (file, contents) in files {
+            let file = dir.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, contents).unwrap();
+        }
+        dir
+    }
+
+    /// Bump the package in a tree with a single config, returning the
+    /// rewritten config.
+    fn write(name: &str, config: &str, source: &str) -> String {
+        let dir = tree(name, &[(config, source)]);
+        Package::get(dir.clone())
+            .unwrap()
+            .write_version("1.1.0")
+            .unwrap();
+        let written = fs::read_to_string(dir.join(config)).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        written
+    }
+
+    #[test]
+    fn detects_kdl() {
+        let line = path::Path::new(".nanparc");
+        assert!(is_kdl(path::Path::new(".nanparc.kdl"), "name a\n"));
+        assert!(is_kdl(line, "name \"a\"\nversion \"1.0.0\"\n"));
+        assert!(is_kdl(
+            line,
+            "packages \"a\"\nchangelog {\n    format \"rst\"\n}\n"
+        ));
+        assert!(!is_kdl(line, "name a\nversion 1.0.0\n"));
+        assert!(!is_kdl(line, "commit-message release \"{releases}\"\n"));
+        assert!(!is_kdl(line, "commit-message chore: release {releases}\n"));
+    }
+
+    #[test]
+    fn loads_line_format_with_quotes() {
+        let dir = tree(
+            "line",
+            &[(
+                ".nanparc",
+                "name a\nversion 1.0.0\ncommit-message release \"{releases}\"\n",
+            )],
+        );
+        let package = Package::get(dir.clone()).unwrap();
+        assert_eq!(package.name.as_deref(), Some("a"));
+        assert_eq!(
+            package.commit_message.as_deref(),
+            Some("release \"{releases}\"")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flattens_kdl_blocks() {
+        let dir = tree(
+            "blocks",
+            &[(
+                ".nanparc",
+                "name \"a\"
+version \"1.0.0\"
+changelog \"docs/CHANGES.md\" {
+    format \"markdown\" \"json\"
+    commits true
+}
+types {
+    added
+    fixed \"Bug Fixes\"
+    internal \"none\"
+}
+",
+            )],
+        );
+        let package = Package::get(dir.clone()).unwrap();
+        assert_eq!(package.version.as_deref(), Some("1.0.0"));
+        assert_eq!(package.changelog, Some(dir.join("docs/CHANGES.md")));
+        assert_eq!(package.changelog_formats, [Format::Markdown, Format::Json]);
+        assert!(package.changelog_commits);
+        assert_eq!(
+            package.change_types,
+            [
+                ChangeType::parse(&["added"]),
+                ChangeType::parse(&["fixed", "Bug", "Fixes"]),
+                ChangeType::parse(&["internal", "none"]),
+            ]
+        );
+        assert_eq!(
+            package.source("changelog-format"),
+            Some(dir.join(".nanparc").as_path())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn inherits_through_superpackages() {
+        let dir = tree(
+            "inherit",
+            &[
+                (
+                    ".nanparc",
+                    "packages group\nlanguage cargo\nscheme pep440\ncustom bump.sh\n",
+                ),
+                ("group/.nanparc", "packages a b\nlanguage node\n"),
+                ("group/a/.nanparc", "name a\nversion 1.0\n"),
+                (
+                    "group/b/.nanparc",
+                    "name b\nversion 1.0\nscheme semver\nlanguage cargo\n",
+                ),
+            ],
+        );
+        let mut packages = Package::get(dir.clone()).unwrap().flatten().unwrap();
+        packages.sort_by(|a, b| a.location.cmp(&b.location));
+        let [a, b] = &packages[..] else {
+            panic!("expected two packages");
+        };
+
+        assert_eq!(a.language.as_deref(), Some("node"));
+        assert_eq!(
+            a.source("language"),
+            Some(dir.join("group/.nanparc").as_path())
+        );
+        assert_eq!(a.scheme, Scheme::Pep440);
+        assert_eq!(a.source("scheme"), Some(dir.join(".nanparc").as_path()));
+        // scripts stay relative to the config that set them
+        assert_eq!(
+            a.custom,
+            Some(dir.join("bump.sh").to_string_lossy().to_string())
+        );
+        assert_eq!(
+            a.source("version"),
+            Some(dir.join("group/a/.nanparc").as_path())
+        );
+
+        assert_eq!(b.language.as_deref(), Some("cargo"));
+        assert_eq!(
+            b.source("language"),
+            Some(dir.join("group/b/.nanparc").as_path())
+        );
+        assert_eq!(b.scheme, Scheme::Semver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn root_only_options() {
+        let dir = tree(
+            "root-only",
+            &[
+                (".nanparc", "packages a\n"),
+                ("a/.nanparc", "name a\nversion 1.0.0\ntag v{version}\n"),
+            ],
+        );
+        let error = Package::get(dir.clone()).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("tag is only read from the root .nanparc"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn excludes_and_skips() {
+        let dir = tree(
+            "exclude",
+            &[
+                (
+                    ".nanparc",
+                    "packages crates/* !crates/*-fixture\nexclude crates/examples\n",
+                ),
+                ("crates/a/.nanparc", "name a\nversion 1.0.0\n"),
+                ("crates/a-fixture/.nanparc", "name fixture\nversion 1.0.0\n"),
+                ("crates/examples/.nanparc", "name examples\nversion 1.0.0\n"),
+                ("crates/docs/README.md", "no config here\n"),
+            ],
+        );
+        let root = Package::get(dir.clone()).unwrap();
+        assert_eq!(
+            root.skipped(),
+            [(dir.join(".nanparc"), dir.join("crates/docs"))]
+        );
+        let names: Vec<String> = root
+            .flatten()
+            .unwrap()
+            .iter()
+            .map(Package::display_name)
+            .collect();
+        assert_eq!(names, ["a"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn version_value_range() {
+        assert_eq!(version_value("version 1.0.0\n"), Some(8..13));
+        assert_eq!(version_value("version\t 1.0.0  \r\n"), Some(9..14));
+        assert_eq!(version_value("versioning-scheme 1.0.0\n"), None);
+        assert_eq!(version_value("# version 1.0.0\n"), None);
+        assert_eq!(version_value("version\n"), None);
+    }
+
+    #[test]
+    fn write_keeps_line_endings() {
+        assert_eq!(
+            write(
+                "crlf",
+                ".nanparc",
+                "name a\r\nversion 1.0.0\r\n\r\nscheme semver\r\n"
+            ),
+            "name a\r\nversion 1.1.0\r\n\r\nscheme semver\r\n"
+        );
+        assert_eq!(
+            write("no-newline", ".nanparc", "name a\nversion 1.0.0"),
+            "name a\nversion 1.1.0"
+        );
+    }
+
+    #[test]
+    fn write_keeps_comments() {
+        assert_eq!(
+            write(
+                "comments",
+                ".nanparc",
+                "# version 0.1.0\nname a\nversion   1.0.0 \n# version 0.2.0\n"
+            ),
+            "# version 0.1.0\nname a\nversion   1.1.0 \n# version 0.2.0\n"
+        );
+        assert_eq!(
+            write(
+                "kdl",
+                ".nanparc.kdl",
+                "// the package\nname \"a\"\nversion \"1.0.0\" // current\n"
+            ),
+            "// the package\nname \"a\"\nversion \"1.1.0\" // current\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+// errors are messages, the caller knows which template failed
+type Result<T> = std::result::Result<T, String>;
+
+/// A value available to a template.
+pub enum Value {
+    Text(String),
+    List(Vec<Context>),
+}
+
+/// Variables available to a template, looked up in order.
+#[derive(Default)]
+pub struct Context(Vec<(&'static str, Value)>);
+
+impl Context {
+    pub fn text(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.0.push((name, Value::Text(value.to_string())));
+        self
+    }
+
+    pub fn optional(self, name: &'static str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.text(name, value),
+            None => self,
+        }
+    }
+
+    pub fn list(mut self, name: &'static str, value: Vec<Context>) -> Self {
+        self.0.push((name, Value::List(value)));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Render a template.
+///
+/// `{name}` is replaced with a variable. `{#name}...{/name}` repeats its
+/// contents for each item of a list, or renders them once if a text variable is
+/// set and non-empty. `{^name}...{/name}` renders its contents only if the
+/// variable is unset or empty. Variables of enclosing blocks stay available.
+/// Lines containing only a block tag are removed from the output. `{{` and
+/// `}}` produce literal braces.
+pub fn render(template: &str, context: &Context) -> Result<String> {
+    let mut standalone = String::new();
+    for line in template.split_inclusive('\n') {
+        let tag = line.trim();
+        if ["{#", "{^", "{/"].iter().any(|open| tag.starts_with(open))
+            && tag.ends_with('}')
+            && tag.matches('}').count() == 1
+        {
+            standalone.push_str(tag);
+        } else {
+            standalone.push_str(line);
+        }
+    }
+
+    let mut out = String::new();
+    render_block(&standalone, &mut vec![context], &mut out)?;
+    Ok(out)
+}
+
+fn lookup<'a>(stack: &[&'a Context], name: &str) -> Option<&'a Value> {
+    stack.iter().rev().find_map(|context| context.get(name))
+}
+
+fn render_block(template: &str, stack: &mut Vec<&Context>, out: &mut String) -> Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(tail) = rest.strip_prefix("{{") {
+            out.push('{');
+            rest = tail;
+            continue;
+        }
+        if let Some(tail) = rest.strip_prefix("}}") {
+            out.push('}');
+            rest = tail;
+            continue;
+        }
+        if rest.starts_with('}') {
+            out.push('}');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let Some(end) = rest.find('}') else {
+            return Err("unclosed { in template".to_string());
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('#').or(tag.strip_prefix('^')) {
+            let inverted = tag.starts_with('^');
+            let (inner, tail) = split_block(rest, name)?;
+            rest = tail;
+
+            match (lookup(stack, name), inverted) {
+                (Some(Value::List(items)), false) => {
+                    for item in items {
+                        stack.push(item);
+                        render_block(inner, stack, out)?;
+                        stack.pop();
+                    }
+                }
+                (Some(Value::Text(text)), false) if !text.is_empty() => {
+                    render_block(inner, stack, out)?
+                }
+                (Some(Value::List(items)), true) if items.is_empty() => {
+                    render_block(inner, stack, out)?
+                }
+                (Some(Value::Text(text)), true) if text.is_empty() => {
+                    render_block(inner, stack, out)?
+                }
+                (None, true) => render_block(inner, stack, out)?,
+                _ => {}
+            }
+        } else if tag.starts_with('/') {
+            return Err(format!("unexpected {{{tag}}} in template"));
+        } else {
+            match lookup(stack, tag.trim()) {
+                Some(Value::Text(text)) => out.push_str(text),
+                Some(Value::List(_)) => {
+                    return Err(format!("{{{tag}}} is a list, use {{#{tag}}}...{{/{tag}}}"))
+                }
+                None => {}
+            }
+        }
+    }
+    out.push_str(rest);
+
+    Ok(())
+}
+
+/// Split `template` at the `{/name}` closing the current block, accounting for
+/// nested blocks of the same name.
+fn split_block<'t>(template: &'t str, name: &str) -> Result<(&'t str, &'t str)> {
+    let open = [format!("{{#{name}}}"), format!("{{^{name}}}")];
+    let close = format!("{{/{name}}}");
+
+    let mut depth = 0;
+    let mut index = 0;
+    while index < template.len() {
+        let rest = &template[index..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            index += 2;
+            continue;
+        }
+        if open.iter().any(|open| rest.starts_with(open.as_str())) {
+            depth += 1;
+        } else if rest.starts_with(close.as_str()) {
+            if depth == 0 {
+                return Ok((&template[..index], &rest[close.len()..]));
+            }
+            depth -= 1;
+        }
+        index += rest.chars().next().unwrap().len_utf8();
+    }
+
+    Err(format!("missing {close} in template"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        Context::default()
+            .text("version", "1.2.0")
+            .text("empty", "")
+            .list(
+                "sections",
+                vec![
+                    Context::default().text("name", "Added").list(
+                        "entries",
+                        vec![
+                            Context::default().text("text", "One"),
+                            Context::default().text("text", "Two"),
+                        ],
+                    ),
+                    Context::default()
+                        .text("name", "Fixed")
+                        .list("entries", vec![Context::default().text("text", "Three")]),
+                ],
+            )
+            .list("none", vec![])
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            render("v{version}{missing} { version }", &context()).unwrap(),
+            "v1.2.0 1.2.0"
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let template = "\
+## {version}
+{#sections}
+
+### {name}
+
+{#entries}
+- {text} ({version})
+{/entries}
+{/sections}
+";
+        assert_eq!(
+            render(template, &context()).unwrap(),
+            "## 1.2.0\n\n### Added\n\n- One (1.2.0)\n- Two (1.2.0)\n\n### Fixed\n\n- Three (1.2.0)\n"
+        );
+    }
+
+    #[test]
+    fn nested_same_name() {
+        let context = Context::default().text("a", "x");
+        assert_eq!(
+            render("{#a}<{#a}[{a}]{/a}>{/a}", &context).unwrap(),
+            "<[x]>"
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let template = "{#version}set{/version}{#empty}no{/empty}{^empty}unset{/empty}\
+                        {^none}no list{/none}{^missing}missing{/missing}{^version}no{/version}";
+        assert_eq!(
+            render(template, &context()).unwrap(),
+            "setunsetno listmissing"
+        );
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(
+            render("{{version}} {{{version}}} a}b", &context()).unwrap(),
+            "{version} {1.2.0} a}b"
+        );
+        assert_eq!(
+            render("{#version}{{/version}}{/version}", &context()).unwrap(),
+            "{/version}"
+        );
+    }
+
+    #[test]
+    fn standalone_tags() {
+        let template = "a\n  {#version}\nb\n  {/version}\nc\n";
+        assert_eq!(render(template, &context()).unwrap(), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(render("{version", &context()).is_err());
+        assert!(render("{#version}x", &context()).is_err());
+        assert!(render("x{/version}", &context()).is_err());
+        assert!(render("{sections}", &context()).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Versioning schemes, and computing the next version of a package.
+
+use crate::error::{IoContext, NanpaError, Result};
+use std::{fmt, path, process};
+
+pub mod pep440;
+
+/// How a package's versions are bumped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scheme {
+    /// Semantic Versioning, the default.
+    #[default]
+    Semver,
+    /// Python's PEP 440.
+    Pep440,
+}
+
+impl Scheme {
+    /// Parse a scheme name, as written in `.nanparc`.
+    pub fn parse(scheme: &str) -> Result<Self> {
+        match scheme {
+            "semver" => Ok(Self::Semver),
+            "pep440" | "python" => Ok(Self::Pep440),
+            unknown => Err(NanpaError::version(format!(
+                "unknown version scheme {unknown}"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Semver => write!(f, "semver"),
+            Self::Pep440 => write!(f, "pep440"),
+        }
+    }
+}
+
+/// Bump level of a change, ordered from least to most significant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    /// No release needed.
+    None,
+    /// Bug fixes.
+    Patch,
+    /// New features.
+    Minor,
+    /// Breaking changes.
+    Major,
+}
+
+impl Bump {
+    /// Parse the bump level of a changeset node.
+    pub fn parse(bump: &str) -> Option<Self> {
+        match bump {
+            "major" => Some(Self::Major),
+            "minor" => Some(Self::Minor),
+            "patch" => Some(Self::Patch),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Patch => write!(f, "patch"),
+            Self::Minor => write!(f, "minor"),
+            Self::Major => write!(f, "major"),
+        }
+    }
+}
+
+/// Manual version change requested through `nanpa bump`.
+pub enum Manual {
+    /// Bump the major version.
+    Major,
+    /// Bump the minor version.
+    Minor,
+    /// Bump the patch version.
+    Patch,
+    /// Start or bump a prerelease of the given kind, e.g. `alpha` or `rc`.
+    Prerelease(String),
+    /// Start or bump a post-release. PEP 440 only.
+    Post,
+    /// Start or bump a developmental release. PEP 440 only.
+    Dev,
+}
+
+/// Apply a manual bump to `version`, according to `scheme`.
+pub fn bump(scheme: Scheme, version: &str, manual: &Manual) -> Result<String> {
+    match scheme {
+        Scheme::Semver => bump_semver(version, manual),
+        Scheme::Pep440 => Ok(pep440::Version::parse(version)?.bump(manual)?.to_string()),
+    }
+}
+
+/// Compute the version following `version` after applying changesets of level `bump`.
+///
+/// Returns `None` if there is nothing to release.
+pub fn next(
+    scheme: Scheme,
+    version: &str,
+    bump: Bump,
+    pre: Option<String>,
+) -> Result<Option<String>> {
+    match scheme {
+        Scheme::Semver => next_semver(version, bump, pre),
+        Scheme::Pep440 => Ok(pep440::Version::parse(version)?
+            .next(bump, pre)?
+            .map(|version| version.to_string())),
+    }
+}
+
+/// Ask a package's `next-version` script for the version following `version`.
+///
+/// The script runs in the package directory with `$VERSION`, `$BUMP` and, if
+/// set, `$PRE` in its environment, and prints the next version to stdout. An
+/// empty output means there is nothing to release.
+pub fn next_script(
+    script: &str,
+    location: &path::Path,
+    version: &str,
+    bump: Bump,
+    pre: Option<String>,
+) -> Result<Option<String>> {
+    let mut command = process::Command::new(location.join(script));
+    command
+        .current_dir(location)
+        .env("VERSION", version)
+        .env("BUMP", bump.to_string())
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::inherit());
+    if let Some(pre) = pre {
+        command.env("PRE", pre);
+    }
+
+    let output = command.output().at(location.join(script))?;
+    if !output.status.success() {
+        return Err(NanpaError::version(format!(
+            "next-version script {script} failed with {}",
+            output.status
+        )));
+    }
+    let next = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if next.is_empty() {
+        return Ok(None);
+    }
+    if next.contains(char::is_whitespace) {
+        return Err(NanpaError::version(format!(
+            "next-version script {script} printed an invalid version: {next}"
+        )));
+    }
+
+    Ok(Some(next))
+}
+
+fn parse_semver(version: &str) -> Result<semver::Version> {
+    match semver::Version::parse(version) {
+        Ok(version) => Ok(version),
+        Err(_) => Err(NanpaError::version(format!(
+            "{version} is not a valid semver version"
+        ))),
+    }
+}
+
+fn prerelease(pre: &str) -> Result<semver::Prerelease> {
+    semver::Prerelease::new(pre)
+        .map_err(|_| NanpaError::version(format!("{pre} is not a valid semver prerelease")))
+}
+
+fn bump_semver(version: &str, manual: &Manual) -> Result<String> {
+    let mut parsed = parse_semver(version)?;
+    match manual {
+        Manual::Major => {
+            parsed.major += 1;
+            parsed.minor = 0;
+            parsed.patch = 0;
+            parsed.pre = semver::Prerelease::EMPTY;
+        }
+        Manual::Minor => {
+            parsed.minor += 1;
+            parsed.patch = 0;
+            parsed.pre = semver::Prerelease::EMPTY;
+        }
+        Manual::Patch => {
+            parsed.patch += 1;
+            parsed.pre = semver::Prerelease::EMPTY;
+        }
+        Manual::Prerelease(x) => parsed.pre = prerelease(x.as_str())?,
+        Manual::Post | Manual::Dev => {
+            return Err(NanpaError::version(
+                "post and dev releases are only supported by the pep440 scheme",
+            ))
+        }
+    };
+
+    Ok(parsed.to_string())
+}
+
+fn next_semver(version: &str, bump: Bump, pre: Option<String>) -> Result<Option<String>> {
+    let mut version = parse_semver(version)?;
+    match bump {
+        Bump::None => {
+            if version.pre.is_empty() {
+                return Ok(None);
+            }
+            if let Some(pre) = pre {
+                let head = version.pre.split_once(".").unwrap_or((&version.pre, "")).0;
+                if head == pre {
+                    return Ok(None);
+                }
+                version.pre = prerelease(format!("{pre}.0").as_str())?;
+            } else {
+                version.pre = semver::Prerelease::EMPTY;
+            }
+        }
+        bump => {
+            let stays = match bump {
+                Bump::Major => version.minor == 0 && version.patch == 0,
+                Bump::Minor => version.patch == 0,
+                _ => true,
+            };
+            match pre {
+                Some(pre) if stays && !version.pre.is_empty() => {
+                    // XXX: assumes prerelease matches `^[\w\d]+\.\d+$`
+                    let (head, tail) = version.pre.split_once(".").unwrap_or((&version.pre, ""));
+                    if pre == head {
+                        let tail = tail.parse::<u64>().unwrap_or(0) + 1;
+                        version.pre = prerelease(format!("{head}.{tail}").as_str())?;
+                    } else {
+                        version.pre = prerelease(format!("{pre}.0").as_str())?;
+                    }
+                }
+                pre => {
+                    match bump {
+                        Bump::Major => {
+                            version.major += 1;
+                            version.minor = 0;
+                            version.patch = 0;
+                        }
+                        Bump::Minor => {
+                            version.minor += 1;
+                            version.patch = 0;
+                        }
+                        _ => version.patch += 1,
+                    }
+                    version.pre = match pre {
+                        Some(pre) => prerelease(format!("{pre}.0").as_str())?,
+                        None => semver::Prerelease::EMPTY,
+                    };
+                }
+            }
+        }
+    };
+
+    Ok(Some(version.to_string()))
+}
@@ -0,0 +1,448 @@
+//! PEP 440 versions.
+
+use super::{Bump, Manual};
+use crate::error::{NanpaError, Result};
+use std::fmt;
+
+/// Kind of a PEP 440 prerelease.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreKind {
+    /// `a`, alpha.
+    Alpha,
+    /// `b`, beta.
+    Beta,
+    /// `rc`, release candidate.
+    Rc,
+}
+
+impl PreKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "a" | "alpha" => Some(Self::Alpha),
+            "b" | "beta" => Some(Self::Beta),
+            "rc" | "c" | "pre" | "preview" => Some(Self::Rc),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Alpha => write!(f, "a"),
+            Self::Beta => write!(f, "b"),
+            Self::Rc => write!(f, "rc"),
+        }
+    }
+}
+
+/// A PEP 440 version, e.g. `1!2.0.0rc1.post2.dev3+local`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    /// Epoch, `1!`, or 0.
+    pub epoch: u64,
+    /// Release segments, e.g. `[2, 0, 0]`.
+    pub release: Vec<u64>,
+    /// Prerelease kind and number.
+    pub pre: Option<(PreKind, u64)>,
+    /// Post-release number.
+    pub post: Option<u64>,
+    /// Developmental release number.
+    pub dev: Option<u64>,
+    /// Local version label, after `+`.
+    pub local: Option<String>,
+}
+
+impl Version {
+    /// Parse and normalize a version.
+    pub fn parse(version: &str) -> Result<Self> {
+        parse(version).ok_or(NanpaError::version(format!(
+            "{version} is not a valid pep440 version"
+        )))
+    }
+
+    fn is_pre(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    fn component(&self, index: usize) -> u64 {
+        self.release.get(index).copied().unwrap_or(0)
+    }
+
+    fn bump_release(&mut self, index: usize) {
+        if self.release.len() <= index {
+            self.release.resize(index + 1, 0);
+        }
+        self.release[index] += 1;
+        for component in self.release.iter_mut().skip(index + 1) {
+            *component = 0;
+        }
+        self.pre = None;
+        self.post = None;
+        self.dev = None;
+        self.local = None;
+    }
+
+    /// Apply a manual bump.
+    pub fn bump(mut self, manual: &Manual) -> Result<Self> {
+        match manual {
+            Manual::Major => self.bump_release(0),
+            Manual::Minor => self.bump_release(1),
+            Manual::Patch => self.bump_release(2),
+            Manual::Prerelease(x) => {
+                let x = x.to_lowercase();
+                let kind = x.trim_end_matches(|c: char| c.is_ascii_digit());
+                let number = &x[kind.len()..];
+                match PreKind::parse(kind.trim_end_matches(['.', '-', '_'])) {
+                    Some(kind) => self.pre = Some((kind, number.parse().unwrap_or(0))),
+                    None => {
+                        return Err(NanpaError::version(format!(
+                            "{x} is not a valid pep440 prerelease (a, b, rc)"
+                        )))
+                    }
+                }
+                self.post = None;
+                self.dev = None;
+            }
+            Manual::Post => {
+                self.post = Some(self.post.map_or(1, |post| post + 1));
+                self.dev = None;
+            }
+            Manual::Dev => self.next_dev(),
+        };
+        self.local = None;
+
+        Ok(self)
+    }
+
+    /// Compute the version after applying changesets of level `bump`, optionally
+    /// as a prerelease (`a`, `b`, `rc`) or developmental release (`dev`).
+    ///
+    /// Returns `None` if there is nothing to release.
+    pub fn next(mut self, bump: Bump, pre: Option<String>) -> Result<Option<Self>> {
+        let pre = match pre.map(|pre| pre.to_lowercase()) {
+            None => None,
+            Some(pre) if pre == "dev" => Some(None),
+            Some(pre) => match PreKind::parse(&pre) {
+                Some(kind) => Some(Some(kind)),
+                None => {
+                    return Err(NanpaError::version(format!(
+                        "{pre} is not a valid pep440 prerelease (a, b, rc, dev)"
+                    )))
+                }
+            },
+        };
+
+        if bump == Bump::None {
+            if !self.is_pre() {
+                return Ok(None);
+            }
+            match pre {
+                Some(None) if self.dev.is_some() => return Ok(None),
+                Some(None) => self.next_dev(),
+                Some(Some(kind))
+                    if self.pre.map(|(k, _)| k) == Some(kind) && self.dev.is_none() =>
+                {
+                    return Ok(None);
+                }
+                Some(kind) => self.set_pre(kind),
+                None => {
+                    self.pre = None;
+                    self.dev = None;
+                }
+            }
+            self.local = None;
+            return Ok(Some(self));
+        }
+
+        let stays = match bump {
+            Bump::Major => self.component(1) == 0 && self.component(2) == 0,
+            Bump::Minor => self.component(2) == 0,
+            _ => true,
+        };
+
+        match pre {
+            Some(kind) if stays && self.is_pre() => match kind {
+                None => self.next_dev(),
+                Some(kind) => match self.pre {
+                    Some((current, number)) if current == kind && self.dev.is_none() => {
+                        self.pre = Some((kind, number + 1))
+                    }
+                    _ => self.set_pre(Some(kind)),
+                },
+            },
+            pre => {
+                self.bump_release(match bump {
+                    Bump::Major => 0,
+                    Bump::Minor => 1,
+                    _ => 2,
+                });
+                if let Some(kind) = pre {
+                    self.set_pre(kind);
+                }
+            }
+        }
+        self.local = None;
+
+        Ok(Some(self))
+    }
+
+    /// Move to the next developmental release. A `.devN` sorts before the
+    /// version it's attached to, so it goes on the next post-release,
+    /// prerelease or patch release, unless this is already one.
+    fn next_dev(&mut self) {
+        match (self.dev, self.post, self.pre) {
+            (Some(dev), _, _) => {
+                self.dev = Some(dev + 1);
+                return;
+            }
+            (None, Some(post), _) => self.post = Some(post + 1),
+            (None, None, Some((kind, number))) => self.pre = Some((kind, number + 1)),
+            (None, None, None) => self.bump_release(2),
+        }
+        self.dev = Some(0);
+    }
+
+    fn set_pre(&mut self, kind: Option<PreKind>) {
+        match kind {
+            Some(kind) => {
+                self.pre = match self.pre {
+                    Some((current, number)) if current == kind && self.dev.is_some() => {
+                        Some((kind, number))
+                    }
+                    _ => Some((kind, 1)),
+                };
+                self.dev = None;
+            }
+            None => self.dev = Some(0),
+        }
+        self.post = None;
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch != 0 {
+            write!(f, "{}!", self.epoch)?;
+        }
+        let release: Vec<String> = self.release.iter().map(|n| n.to_string()).collect();
+        write!(f, "{}", release.join("."))?;
+        if let Some((kind, number)) = self.pre {
+            write!(f, "{kind}{number}")?;
+        }
+        if let Some(post) = self.post {
+            write!(f, ".post{post}")?;
+        }
+        if let Some(dev) = self.dev {
+            write!(f, ".dev{dev}")?;
+        }
+        if let Some(local) = &self.local {
+            write!(f, "+{local}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse(version: &str) -> Option<Version> {
+    let version = version.trim().to_lowercase();
+    let version = version.strip_prefix('v').unwrap_or(&version);
+    let (version, local) = match version.split_once('+') {
+        Some((_, "")) => return None,
+        Some((version, local)) => (version, Some(local.replace(['-', '_'], "."))),
+        None => (version, None),
+    };
+    let (epoch, mut rest) = match version.split_once('!') {
+        Some((epoch, rest)) => (epoch.parse().ok()?, rest),
+        None => (0, version),
+    };
+
+    let mut release = vec![];
+    loop {
+        let (number, tail) = take_number(rest)?;
+        release.push(number);
+        rest = tail;
+        match rest.strip_prefix('.') {
+            Some(tail) if tail.starts_with(|c: char| c.is_ascii_digit()) => rest = tail,
+            _ => break,
+        }
+    }
+
+    let mut pre = None;
+    let mut post = None;
+    let mut dev = None;
+
+    let tail = rest.trim_start_matches(['.', '-', '_']);
+    let word = tail.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    if let Some(kind) = PreKind::parse(&tail[..tail.len() - word.len()]) {
+        let (number, tail) = take_optional_number(word);
+        pre = Some((kind, number));
+        rest = tail;
+    }
+
+    if let Some(tail) = rest.strip_prefix('-') {
+        if let Some((number, tail)) = take_number(tail) {
+            post = Some(number);
+            rest = tail;
+        }
+    }
+    if post.is_none() {
+        let tail = rest.trim_start_matches(['.', '-', '_']);
+        for word in ["post", "rev", "r"] {
+            if let Some(tail) = tail.strip_prefix(word) {
+                let (number, tail) = take_optional_number(tail);
+                post = Some(number);
+                rest = tail;
+                break;
+            }
+        }
+    }
+
+    let tail = rest.trim_start_matches(['.', '-', '_']);
+    if let Some(tail) = tail.strip_prefix("dev") {
+        let (number, tail) = take_optional_number(tail);
+        dev = Some(number);
+        rest = tail;
+    }
+
+    if !rest.is_empty() {
+        return None;
+    }
+
+    Some(Version {
+        epoch,
+        release,
+        pre,
+        post,
+        dev,
+        local,
+    })
+}
+
+fn take_number(s: &str) -> Option<(u64, &str)> {
+    let tail = s.trim_start_matches(|c: char| c.is_ascii_digit());
+    let number = s[..s.len() - tail.len()].parse().ok()?;
+    Some((number, tail))
+}
+
+fn take_optional_number(s: &str) -> (u64, &str) {
+    take_number(s.trim_start_matches(['.', '-', '_'])).unwrap_or((0, s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sort key following the PEP 440 ordering rules for the fields we produce.
+    fn key(version: &str) -> impl Ord {
+        let v = Version::parse(version).unwrap();
+        let mut release = v.release.clone();
+        while release.last() == Some(&0) {
+            release.pop();
+        }
+        // A bare `.devN` sorts before any prerelease of the same release.
+        let pre = match (v.pre, v.post, v.dev) {
+            (None, None, Some(_)) => (0, 0, 0),
+            (None, _, _) => (2, 0, 0),
+            (Some((kind, number)), _, _) => (1, kind as u64, number),
+        };
+        let post = v.post.map_or((0, 0), |post| (1, post));
+        let dev = v.dev.map_or((1, 0), |dev| (0, dev));
+        (v.epoch, release, pre, post, dev)
+    }
+
+    fn bump(version: &str, manual: Manual) -> String {
+        Version::parse(version)
+            .unwrap()
+            .bump(&manual)
+            .unwrap()
+            .to_string()
+    }
+
+    fn next(version: &str, bump: Bump, pre: Option<&str>) -> Option<String> {
+        Version::parse(version)
+            .unwrap()
+            .next(bump, pre.map(String::from))
+            .unwrap()
+            .map(|v| v.to_string())
+    }
+
+    #[test]
+    fn round_trip() {
+        let v = Version::parse("1!2.0rc1.post2.dev3+local").unwrap();
+        assert_eq!(v.epoch, 1);
+        assert_eq!(v.release, [2, 0]);
+        assert_eq!(v.pre, Some((PreKind::Rc, 1)));
+        assert_eq!(v.post, Some(2));
+        assert_eq!(v.dev, Some(3));
+        assert_eq!(v.local.as_deref(), Some("local"));
+        assert_eq!(v.to_string(), "1!2.0rc1.post2.dev3+local");
+    }
+
+    #[test]
+    fn normalizes() {
+        let normalize = |v: &str| Version::parse(v).unwrap().to_string();
+        assert_eq!(normalize("v1.0-alpha.2"), "1.0a2");
+        assert_eq!(normalize("1.0.preview"), "1.0rc0");
+        assert_eq!(normalize("1.0-3"), "1.0.post3");
+        assert_eq!(normalize("1.0.rev"), "1.0.post0");
+        assert_eq!(normalize("1.0-dev"), "1.0.dev0");
+        assert_eq!(normalize("1.0+Ubuntu-1"), "1.0+ubuntu.1");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for version in ["", "1.", "1.0foo", "a1.0", "1!", "1.0+"] {
+            assert!(Version::parse(version).is_err(), "{version}");
+        }
+    }
+
+    #[test]
+    fn manual_bumps_increase() {
+        let cases = [
+            ("1.2.0", Manual::Dev, "1.2.1.dev0"),
+            ("1.2.1.dev0", Manual::Dev, "1.2.1.dev1"),
+            ("1.2.0rc1", Manual::Dev, "1.2.0rc2.dev0"),
+            ("1.2.0.post1", Manual::Dev, "1.2.0.post2.dev0"),
+            ("1.2.0", Manual::Post, "1.2.0.post1"),
+            ("1.2.0.post1.dev0", Manual::Post, "1.2.0.post2"),
+            ("1.2.0rc1+local", Manual::Patch, "1.2.1"),
+            ("1!2.0", Manual::Major, "1!3.0"),
+        ];
+        for (version, manual, expected) in cases {
+            let bumped = bump(version, manual);
+            assert_eq!(bumped, expected);
+            assert!(key(&bumped) > key(version), "{bumped} <= {version}");
+        }
+    }
+
+    #[test]
+    fn next_increases() {
+        let cases = [
+            ("1.2.0", Bump::Patch, None, "1.2.1"),
+            ("1.2.0", Bump::Minor, Some("dev"), "1.3.0.dev0"),
+            ("1.2.0", Bump::Minor, Some("rc"), "1.3.0rc1"),
+            ("1.3.0rc1", Bump::Patch, Some("rc"), "1.3.0rc2"),
+            ("1.3.0rc1", Bump::Patch, Some("dev"), "1.3.0rc2.dev0"),
+            ("1.3.0rc2.dev0", Bump::Patch, Some("rc"), "1.3.0rc2"),
+            ("1.3.0a2", Bump::Minor, Some("b"), "1.3.0b1"),
+            ("1.3.0rc1", Bump::None, Some("dev"), "1.3.0rc2.dev0"),
+            ("1.3.0rc1", Bump::None, None, "1.3.0"),
+            ("1.3.0.dev4", Bump::Patch, None, "1.3.1"),
+            ("1.3.1rc1", Bump::Minor, Some("rc"), "1.4.0rc1"),
+        ];
+        for (version, bump, pre, expected) in cases {
+            let next = next(version, bump, pre).unwrap();
+            assert_eq!(next, expected);
+            assert!(key(&next) > key(version), "{next} <= {version}");
+        }
+    }
+
+    #[test]
+    fn next_without_changes() {
+        assert_eq!(next("1.2.0", Bump::None, None), None);
+        assert_eq!(next("1.2.0", Bump::None, Some("dev")), None);
+        assert_eq!(next("1.3.0rc1", Bump::None, Some("rc")), None);
+        assert_eq!(next("1.3.0.dev0", Bump::None, Some("dev")), None);
+    }
+}